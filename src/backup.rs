@@ -0,0 +1,390 @@
+//! Single-file encrypted vault backups.
+//!
+//! An archive starts with a plaintext header (`MAGIC | salt | Argon2id
+//! parameters`) followed by one ciphertext of the payload, encrypted under a
+//! key derived from a backup passphrase. The archive therefore restores
+//! without the original config. The payload is `manifest length (u32 LE) |
+//! manifest TOML | entry data`, and the manifest records the SHA-256 of every
+//! entry so a restore can validate each file before anything is written.
+
+use crate::config::Config;
+use crate::crypto::{sha256_hex, Binding, Crypto, KdfParams, SALT_LEN};
+use crate::db::Database;
+use crate::error::{NoterError, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
+
+const MAGIC: &[u8; 8] = b"NTRSBAK\x01";
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + 12;
+/// Upper bounds on the Argon2id costs an archive may ask for, so a corrupt
+/// or hostile header cannot make a restore allocate gigabytes.
+const MAX_KDF: KdfParams = KdfParams {
+    memory_kib: 1024 * 1024,
+    iterations: 64,
+    parallelism: 16,
+};
+const BINDING: Binding = Binding {
+    id: "backup",
+    context: "",
+};
+
+/// Archive entry holding the database snapshot.
+pub const DB_ENTRY: &str = "noters.db";
+/// Prefix of archive entries holding encrypted note files.
+pub const NOTES_PREFIX: &str = "notes/";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub created_at: String,
+    pub schema_version: i64,
+    pub use_passphrase: bool,
+    /// The legacy `encryption_key`, for vaults that are not passphrase
+    /// protected; without it the notes could not be read after a restore.
+    pub encryption_key: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+impl Drop for Manifest {
+    fn drop(&mut self) {
+        self.encryption_key.zeroize();
+    }
+}
+
+/// Collects entries in memory and seals them into an archive file.
+pub struct ArchiveWriter {
+    manifest: Manifest,
+    data: Zeroizing<Vec<u8>>,
+}
+
+impl ArchiveWriter {
+    pub fn new(schema_version: i64, use_passphrase: bool, encryption_key: Option<&str>) -> Self {
+        Self {
+            manifest: Manifest {
+                created_at: Local::now().to_rfc3339(),
+                schema_version,
+                use_passphrase,
+                encryption_key: encryption_key.map(str::to_string),
+                entries: Vec::new(),
+            },
+            data: Zeroizing::new(Vec::new()),
+        }
+    }
+
+    pub fn add(&mut self, name: &str, bytes: &[u8]) {
+        self.manifest.entries.push(Entry {
+            name: name.to_string(),
+            size: bytes.len() as u64,
+            sha256: sha256_hex(bytes),
+        });
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Encrypts the archive under `passphrase` and writes it to `path`.
+    pub fn finish(self, path: &Path, passphrase: &str, params: &KdfParams) -> Result<()> {
+        if !within_bounds(params) {
+            return Err(NoterError::Config(format!(
+                "Backup KDF parameters exceed the limits a restore accepts ({} KiB, {} iterations, {} lanes)",
+                MAX_KDF.memory_kib, MAX_KDF.iterations, MAX_KDF.parallelism
+            )));
+        }
+        let manifest = Zeroizing::new(
+            toml::to_string(&self.manifest).map_err(|e| NoterError::Config(e.to_string()))?,
+        );
+        let manifest_len = u32::try_from(manifest.len())
+            .map_err(|_| NoterError::InvalidInput("Backup manifest too large".to_string()))?;
+
+        let mut payload = Zeroizing::new(Vec::with_capacity(4 + manifest.len() + self.data.len()));
+        payload.extend_from_slice(&manifest_len.to_le_bytes());
+        payload.extend_from_slice(manifest.as_bytes());
+        payload.extend_from_slice(&self.data);
+
+        let salt = Crypto::generate_salt();
+        let crypto = Crypto::from_passphrase(passphrase, &salt, params)?;
+        let sealed = crypto.encrypt_bound(&payload, BINDING)?;
+
+        let mut archive = Vec::with_capacity(HEADER_LEN + sealed.len());
+        archive.extend_from_slice(MAGIC);
+        archive.extend_from_slice(&salt);
+        archive.extend_from_slice(&params.memory_kib.to_le_bytes());
+        archive.extend_from_slice(&params.iterations.to_le_bytes());
+        archive.extend_from_slice(&params.parallelism.to_le_bytes());
+        archive.extend_from_slice(sealed.as_bytes());
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        std::io::Write::write_all(&mut file, &archive)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// A decrypted archive whose entries have all been checked against the
+/// manifest.
+pub struct Archive {
+    pub manifest: Manifest,
+    payload: Zeroizing<Vec<u8>>,
+    offsets: Vec<usize>,
+}
+
+impl Archive {
+    pub fn open(path: &Path, passphrase: &str) -> Result<Self> {
+        let archive = fs::read(path)?;
+        if archive.len() < HEADER_LEN || !archive.starts_with(MAGIC) {
+            return Err(NoterError::InvalidInput(format!(
+                "{} is not a noters backup",
+                path.display()
+            )));
+        }
+
+        let (salt, params) = archive[MAGIC.len()..HEADER_LEN].split_at(SALT_LEN);
+        let param = |i: usize| u32::from_le_bytes(params[i * 4..i * 4 + 4].try_into().unwrap());
+        let params = KdfParams {
+            memory_kib: param(0),
+            iterations: param(1),
+            parallelism: param(2),
+        };
+        if !within_bounds(&params) {
+            return Err(NoterError::InvalidInput("Corrupt backup".to_string()));
+        }
+        let sealed = std::str::from_utf8(&archive[HEADER_LEN..])
+            .map_err(|_| NoterError::InvalidInput("Corrupt backup".to_string()))?;
+
+        let crypto = Crypto::from_passphrase(passphrase, salt, &params)?;
+        let payload = crypto
+            .decrypt_bound(sealed, BINDING)
+            .map_err(|_| {
+                NoterError::InvalidInput(
+                    "Incorrect backup passphrase, or the archive is corrupt".to_string(),
+                )
+            })?;
+
+        let corrupt = || NoterError::InvalidInput("Corrupt backup".to_string());
+        let manifest_len = payload
+            .get(..4)
+            .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+            .ok_or_else(corrupt)?;
+        let manifest = payload
+            .get(4..4 + manifest_len)
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+            .ok_or_else(corrupt)?;
+        let manifest: Manifest = toml::from_str(manifest)
+            .map_err(|e| NoterError::InvalidInput(format!("Corrupt backup manifest: {}", e)))?;
+
+        let mut offsets = Vec::with_capacity(manifest.entries.len());
+        let mut offset = 4 + manifest_len;
+        for entry in &manifest.entries {
+            let end = offset + entry.size as usize;
+            let bytes = payload.get(offset..end).ok_or_else(corrupt)?;
+            if sha256_hex(bytes) != entry.sha256 {
+                return Err(NoterError::InvalidInput(format!(
+                    "Backup entry {} failed its integrity check",
+                    entry.name
+                )));
+            }
+            offsets.push(offset);
+            offset = end;
+        }
+        if offset != payload.len() {
+            return Err(corrupt());
+        }
+
+        Ok(Self {
+            manifest,
+            payload,
+            offsets,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.manifest
+            .entries
+            .iter()
+            .zip(&self.offsets)
+            .find(|(entry, _)| entry.name == name)
+            .map(|(entry, &offset)| &self.payload[offset..offset + entry.size as usize])
+    }
+
+    /// Encrypted note files as `(filename, contents)`.
+    pub fn notes(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.manifest
+            .entries
+            .iter()
+            .zip(&self.offsets)
+            .filter_map(|(entry, &offset)| {
+                let filename = entry.name.strip_prefix(NOTES_PREFIX)?;
+                Some((filename, &self.payload[offset..offset + entry.size as usize]))
+            })
+    }
+}
+
+/// Restores `archive` into `notes_dir` and `db_path`. Refuses to overwrite
+/// an existing vault unless `force` is set. Returns the number of notes
+/// restored.
+///
+/// The config is only updated when restoring over the configured vault, or
+/// with `switch` to make the restored vault the configured one. Switching is
+/// refused if it would replace the key of a configured vault that still
+/// holds data.
+pub fn restore(
+    config: &mut Config,
+    archive: &Archive,
+    notes_dir: PathBuf,
+    db_path: PathBuf,
+    force: bool,
+    switch: bool,
+) -> Result<usize> {
+    let db_bytes = archive
+        .get(DB_ENTRY)
+        .ok_or_else(|| NoterError::InvalidInput("Backup has no database".to_string()))?;
+    for (filename, _) in archive.notes() {
+        if filename.is_empty() || filename.contains(['/', '\\']) || filename.starts_with('.') {
+            return Err(NoterError::InvalidInput(format!(
+                "Backup contains an invalid note filename: {}",
+                filename
+            )));
+        }
+    }
+
+    let configured = same_path(&notes_dir, &config.notes_dir) && same_path(&db_path, &config.db_path);
+    let replaces_key = archive
+        .manifest
+        .encryption_key
+        .as_deref()
+        .is_some_and(|key| key != config.encryption_key.as_str());
+    if switch && !configured && replaces_key && occupied(&config.notes_dir, &config.db_path) {
+        return Err(NoterError::InvalidInput(format!(
+            "Switching to the restored vault would lose the key of the vault at {}; restore without --switch instead",
+            config.notes_dir.display()
+        )));
+    }
+
+    if occupied(&notes_dir, &db_path) && !force {
+        return Err(NoterError::InvalidInput(format!(
+            "A vault already exists at {} or {}; pass --force to replace it",
+            notes_dir.display(),
+            db_path.display()
+        )));
+    }
+
+    // Validate the database before touching the target vault.
+    if let Some(parent) = db_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let staged_db = db_path.with_extension("db.restore");
+    fs::write(&staged_db, db_bytes)?;
+    let check = Database::open(staged_db.clone()).and_then(|db| {
+        db.pending_migrations()?;
+        let filenames: Vec<String> = db.get_all_notes()?.into_iter().map(|n| n.filename).collect();
+        match filenames
+            .iter()
+            .find(|filename| !archive.notes().any(|(name, _)| name == filename.as_str()))
+        {
+            Some(missing) => Err(NoterError::InvalidInput(format!(
+                "Backup is missing note file {}",
+                missing
+            ))),
+            None => Ok(()),
+        }
+    });
+    if let Err(e) = check {
+        fs::remove_file(&staged_db)?;
+        return Err(e);
+    }
+
+    // The notes are written beside the vault and swapped in whole, so files
+    // of the replaced vault that the backup doesn't know are not left behind.
+    let staged_notes = sibling(&notes_dir, "restore")?;
+    let replaced_notes = sibling(&notes_dir, "replaced")?;
+    let count = match stage_notes(archive, &notes_dir, &staged_notes) {
+        Ok(count) => count,
+        Err(e) => {
+            fs::remove_file(&staged_db)?;
+            return Err(e);
+        }
+    };
+    if notes_dir.exists() {
+        if replaced_notes.exists() {
+            fs::remove_dir_all(&replaced_notes)?;
+        }
+        if let Err(e) = fs::rename(&notes_dir, &replaced_notes) {
+            fs::remove_dir_all(&staged_notes)?;
+            fs::remove_file(&staged_db)?;
+            return Err(e.into());
+        }
+    }
+    fs::rename(&staged_notes, &notes_dir)?;
+    fs::rename(&staged_db, &db_path)?;
+    if replaced_notes.exists() {
+        fs::remove_dir_all(&replaced_notes)?;
+    }
+
+    if !configured && !switch {
+        return Ok(count);
+    }
+    config.notes_dir = notes_dir;
+    config.db_path = db_path;
+    config.use_passphrase = archive.manifest.use_passphrase;
+    if let Some(key) = &archive.manifest.encryption_key {
+        config.encryption_key = Zeroizing::new(key.clone());
+    }
+    config.save()?;
+    Ok(count)
+}
+
+/// Writes the archive's notes into `staged`, which takes on the
+/// permissions of the existing `notes_dir`.
+fn stage_notes(archive: &Archive, notes_dir: &Path, staged: &Path) -> Result<usize> {
+    if staged.exists() {
+        fs::remove_dir_all(staged)?;
+    }
+    fs::create_dir_all(staged)?;
+    if let Ok(metadata) = fs::metadata(notes_dir) {
+        fs::set_permissions(staged, metadata.permissions())?;
+    }
+    let mut count = 0;
+    for (filename, contents) in archive.notes() {
+        fs::write(staged.join(filename), contents)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// `<dir>.<suffix>` next to `dir`.
+fn sibling(dir: &Path, suffix: &str) -> Result<PathBuf> {
+    let name = dir.file_name().ok_or_else(|| {
+        NoterError::InvalidInput(format!("Cannot restore into {}", dir.display()))
+    })?;
+    let mut name = name.to_os_string();
+    name.push(format!(".{}", suffix));
+    Ok(dir.with_file_name(name))
+}
+
+fn within_bounds(params: &KdfParams) -> bool {
+    (1..=MAX_KDF.memory_kib).contains(&params.memory_kib)
+        && (1..=MAX_KDF.iterations).contains(&params.iterations)
+        && (1..=MAX_KDF.parallelism).contains(&params.parallelism)
+}
+
+fn occupied(notes_dir: &Path, db_path: &Path) -> bool {
+    db_path.exists() || fs::read_dir(notes_dir).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    a == b || matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
@@ -1,18 +1,32 @@
+use crate::crypto::KdfParams;
 use crate::error::{NoterError, Result};
 use dirs::home_dir;
 use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
 use std::path::PathBuf;
+use zeroize::Zeroizing;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 pub struct Config {
     pub notes_dir: PathBuf,
     pub db_path: PathBuf,
     pub default_extension: String,
     pub editor: Option<String>,
-    pub encryption_key: String,
+    #[serde(with = "secret")]
+    pub encryption_key: Zeroizing<String>,
     pub export_dir: Option<PathBuf>,
+    /// Derive the vault key from a master passphrase (Argon2id) instead of
+    /// `encryption_key`. Only takes effect when the vault is first created.
+    #[serde(default)]
+    pub use_passphrase: bool,
+    /// Command whose stdout is used as the passphrase, e.g. a password manager.
+    #[serde(default)]
+    pub passphrase_command: Option<String>,
+    /// Argon2id cost parameters used when a new passphrase vault is created.
+    #[serde(default)]
+    pub kdf: KdfParams,
 }
 
 impl Config {
@@ -26,7 +40,7 @@ impl Config {
             return Ok(config);
         }
 
-        let contents = std::fs::read_to_string(config_path)?;
+        let contents = Zeroizing::new(std::fs::read_to_string(config_path)?);
         toml::from_str(&contents).map_err(|e| NoterError::Config(e.to_string()))
     }
 
@@ -38,8 +52,10 @@ impl Config {
         fs::create_dir_all(&self.notes_dir)?;
         fs::create_dir_all(self.db_path.parent().unwrap())?;
 
-        let toml = toml::to_string_pretty(self).map_err(|e| NoterError::Config(e.to_string()))?;
-        fs::write(config_path, toml)?;
+        let toml = Zeroizing::new(
+            toml::to_string_pretty(self).map_err(|e| NoterError::Config(e.to_string()))?,
+        );
+        fs::write(config_path, toml.as_bytes())?;
 
         Ok(())
     }
@@ -50,12 +66,30 @@ impl Config {
             .ok_or(NoterError::HomeDirNotFound)
     }
 
-    fn generate_encryption_key() -> String {
-        rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(32)
-            .map(char::from)
-            .collect()
+    pub(crate) fn generate_encryption_key() -> Zeroizing<String> {
+        Zeroizing::new(
+            rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("notes_dir", &self.notes_dir)
+            .field("db_path", &self.db_path)
+            .field("default_extension", &self.default_extension)
+            .field("editor", &self.editor)
+            .field("encryption_key", &"<redacted>")
+            .field("export_dir", &self.export_dir)
+            .field("use_passphrase", &self.use_passphrase)
+            .field("passphrase_command", &self.passphrase_command)
+            .field("kdf", &self.kdf)
+            .finish()
     }
 }
 
@@ -70,7 +104,24 @@ impl Default for Config {
             editor: None,
             encryption_key: Self::generate_encryption_key(),
             export_dir: Some(noters_dir.join("exports")),
+            use_passphrase: false,
+            passphrase_command: None,
+            kdf: KdfParams::default(),
         }
     }
 }
 
+
+/// Serde glue for keys held in [`Zeroizing`] buffers.
+mod secret {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use zeroize::Zeroizing;
+
+    pub fn serialize<S: Serializer>(value: &Zeroizing<String>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Zeroizing<String>, D::Error> {
+        String::deserialize(deserializer).map(Zeroizing::new)
+    }
+}
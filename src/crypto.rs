@@ -1,52 +1,366 @@
 use crate::error::{NoterError, Result};
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256Gcm, Nonce,
 };
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+pub const SALT_LEN: usize = 16;
+
+/// Every ciphertext starts with `MAGIC | version | kdf id | cipher id | nonce`.
+/// Version 2 adds `id length | id` naming the note the data belongs to. The
+/// header is authenticated as associated data. Files without the magic
+/// prefix are the original headerless `nonce || ciphertext` format.
+const MAGIC: &[u8; 4] = b"NTRS";
+const FORMAT_UNBOUND: u8 = 1;
+const FORMAT_BOUND: u8 = 2;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 3 + NONCE_LEN;
+
+/// Ties a ciphertext to the note it belongs to. `id` is recorded in the
+/// header so a mismatch can be reported; `context` (e.g. the filename) is
+/// only mixed into the associated data.
+#[derive(Debug, Clone, Copy)]
+pub struct Binding<'a> {
+    pub id: &'a str,
+    pub context: &'a str,
+}
+
+/// Identifies how the key was derived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    Sha256 = 1,
+    Argon2id = 2,
+}
+
+impl Kdf {
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Kdf::Sha256),
+            2 => Some(Kdf::Argon2id),
+            _ => None,
+        }
+    }
+}
+
+/// Identifies the AEAD used for the payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cipher {
+    Aes256Gcm = 1,
+}
+
+impl Cipher {
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Cipher::Aes256Gcm),
+            _ => None,
+        }
+    }
+}
+
+struct Envelope<'a> {
+    header: &'a [u8],
+    kdf: Kdf,
+    nonce: &'a [u8],
+    bound_id: Option<&'a [u8]>,
+    ciphertext: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    /// Returns `None` when `data` has no envelope header at all.
+    fn parse(data: &'a [u8]) -> Option<Result<Self>> {
+        if !data.starts_with(MAGIC) {
+            return None;
+        }
+        if data.len() < HEADER_LEN {
+            return Some(Err(NoterError::Encryption("Truncated envelope".to_string())));
+        }
+
+        let version = data[4];
+        if version != FORMAT_UNBOUND && version != FORMAT_BOUND {
+            return Some(Err(NoterError::Encryption(format!(
+                "Unsupported format version {}",
+                version
+            ))));
+        }
+        let Some(kdf) = Kdf::from_id(data[5]) else {
+            return Some(Err(NoterError::Encryption(format!("Unknown KDF id {}", data[5]))));
+        };
+        if Cipher::from_id(data[6]).is_none() {
+            return Some(Err(NoterError::Encryption(format!(
+                "Unknown cipher id {}",
+                data[6]
+            ))));
+        }
+
+        let mut header_len = HEADER_LEN;
+        let mut bound_id = None;
+        if version == FORMAT_BOUND {
+            let id_len = match data.get(HEADER_LEN) {
+                Some(len) => *len as usize,
+                None => return Some(Err(NoterError::Encryption("Truncated envelope".to_string()))),
+            };
+            header_len += 1 + id_len;
+            match data.get(HEADER_LEN + 1..header_len) {
+                Some(id) => bound_id = Some(id),
+                None => return Some(Err(NoterError::Encryption("Truncated envelope".to_string()))),
+            }
+        }
+
+        let (header, ciphertext) = data.split_at(header_len);
+        Some(Ok(Self {
+            header,
+            kdf,
+            nonce: &header[MAGIC.len() + 3..HEADER_LEN],
+            bound_id,
+            ciphertext,
+        }))
+    }
+}
+
+/// Converts decrypted bytes to text without leaving an unwiped copy behind.
+pub fn into_string(mut bytes: Zeroizing<Vec<u8>>) -> Result<Zeroizing<String>> {
+    match String::from_utf8(std::mem::take(&mut *bytes)) {
+        Ok(text) => Ok(Zeroizing::new(text)),
+        Err(e) => {
+            let message = e.utf8_error().to_string();
+            drop(Zeroizing::new(e.into_bytes()));
+            Err(NoterError::Encryption(message))
+        }
+    }
+}
+
+/// Hex-encoded SHA-256, used for integrity checks in backups and exports.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Cost parameters for the Argon2id passphrase KDF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
 
 pub struct Crypto {
     cipher: Aes256Gcm,
+    kdf: Kdf,
+    key_check: String,
 }
 
 impl Crypto {
+    /// Legacy scheme: the key is a single SHA-256 over the configured key string.
     pub fn new(key: &str) -> Self {
         let mut hasher = Sha256::new();
         hasher.update(key.as_bytes());
-        let key = hasher.finalize();
-        let cipher = Aes256Gcm::new_from_slice(&key).expect("Invalid key length");
-        Self { cipher }
+        let mut key = Zeroizing::new([0u8; 32]);
+        key.copy_from_slice(&hasher.finalize());
+        Self::from_key_bytes(key.as_slice(), Kdf::Sha256)
+    }
+
+    /// Derives the key from a passphrase with Argon2id.
+    pub fn from_passphrase(passphrase: &str, salt: &[u8], params: &KdfParams) -> Result<Self> {
+        let params = Params::new(
+            params.memory_kib,
+            params.iterations,
+            params.parallelism,
+            Some(32),
+        )
+        .map_err(|e| NoterError::Encryption(e.to_string()))?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        argon2
+            .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+            .map_err(|e| NoterError::Encryption(e.to_string()))?;
+        Ok(Self::from_key_bytes(key.as_slice(), Kdf::Argon2id))
+    }
+
+    /// The AES key schedule inside `cipher` is wiped on drop (the `zeroize`
+    /// features of `aes` and `aes-gcm`); callers wipe their copy of `key`.
+    fn from_key_bytes(key: &[u8], kdf: Kdf) -> Self {
+        let cipher = Aes256Gcm::new_from_slice(key).expect("Invalid key length");
+
+        let mut hasher = Sha256::new();
+        hasher.update(b"noters-key-check\0");
+        hasher.update(key);
+        let key_check = BASE64.encode(hasher.finalize());
+
+        Self {
+            cipher,
+            kdf,
+            key_check,
+        }
+    }
+
+    pub fn generate_salt() -> [u8; SALT_LEN] {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill(&mut salt);
+        salt
+    }
+
+    /// A one-way fingerprint of the derived key, used to tell whether a
+    /// passphrase is correct without attempting to decrypt a note.
+    pub fn key_check(&self) -> &str {
+        &self.key_check
     }
 
     pub fn encrypt(&self, data: &[u8]) -> Result<String> {
-        let mut rng = rand::thread_rng();
-        let mut nonce_bytes = [0u8; 12];
-        rng.fill(&mut nonce_bytes);
-        let nonce = Nonce::from_slice(&nonce_bytes);
+        self.seal(data, None)
+    }
+
+    /// Encrypts `data` so it only decrypts under the same [`Binding`].
+    pub fn encrypt_bound(&self, data: &[u8], binding: Binding) -> Result<String> {
+        self.seal(data, Some(binding))
+    }
+
+    fn seal(&self, data: &[u8], binding: Option<Binding>) -> Result<String> {
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        rand::thread_rng().fill(&mut nonce_bytes);
+
+        let mut envelope = Vec::with_capacity(HEADER_LEN + data.len() + 64);
+        envelope.extend_from_slice(MAGIC);
+        envelope.push(if binding.is_some() { FORMAT_BOUND } else { FORMAT_UNBOUND });
+        envelope.push(self.kdf as u8);
+        envelope.push(Cipher::Aes256Gcm as u8);
+        envelope.extend_from_slice(&nonce_bytes);
+
+        if let Some(binding) = binding {
+            let id_len = u8::try_from(binding.id.len())
+                .map_err(|_| NoterError::Encryption("Binding id too long".to_string()))?;
+            envelope.push(id_len);
+            envelope.extend_from_slice(binding.id.as_bytes());
+        }
+        let mut aad = envelope.clone();
+        if let Some(binding) = binding {
+            aad.extend_from_slice(binding.context.as_bytes());
+        }
 
         let ciphertext = self
             .cipher
-            .encrypt(nonce, data)
+            .encrypt(
+                Nonce::from_slice(&nonce_bytes),
+                Payload {
+                    msg: data,
+                    aad: &aad,
+                },
+            )
             .map_err(|e| NoterError::Encryption(e.to_string()))?;
 
-        let mut combined = nonce_bytes.to_vec();
-        combined.extend(ciphertext);
-        Ok(BASE64.encode(combined))
+        envelope.extend(ciphertext);
+        Ok(BASE64.encode(envelope))
     }
 
-    pub fn decrypt(&self, data: &str) -> Result<Vec<u8>> {
+    /// Plaintext is returned in a buffer that is wiped when dropped.
+    pub fn decrypt(&self, data: &str) -> Result<Zeroizing<Vec<u8>>> {
+        self.open(data, None)
+    }
+
+    /// Decrypts data written by [`Crypto::encrypt_bound`], failing with
+    /// [`NoterError::BindingMismatch`] if it belongs to a different note.
+    /// Unbound and legacy data are rejected, so they cannot stand in for a
+    /// note's own ciphertext.
+    pub fn decrypt_bound(&self, data: &str, binding: Binding) -> Result<Zeroizing<Vec<u8>>> {
+        let decoded = Self::decode(data)?;
+        match Envelope::parse(&decoded) {
+            Some(Ok(envelope)) if envelope.bound_id.is_some() => self
+                .decrypt_envelope(&envelope, Some(binding))
+                .map(Zeroizing::new),
+            Some(Err(e)) => Err(e),
+            _ => Err(NoterError::Encryption("Data is not bound to a note".to_string())),
+        }
+    }
+
+    /// Like [`Crypto::decrypt_bound`], but also accepts unbound and legacy
+    /// data. Only for re-encrypting data written before bindings existed.
+    pub fn decrypt_for_upgrade(&self, data: &str, binding: Binding) -> Result<Zeroizing<Vec<u8>>> {
+        self.open(data, Some(binding))
+    }
+
+    fn decode(data: &str) -> Result<Vec<u8>> {
+        BASE64
+            .decode(data.trim())
+            .map_err(|e| NoterError::Encryption(e.to_string()))
+    }
+
+    fn open(&self, data: &str, binding: Option<Binding>) -> Result<Zeroizing<Vec<u8>>> {
         // This function brought me to the brink of insanity.
-        let decoded = BASE64
-            .decode(data)
-            .map_err(|e| NoterError::Encryption(e.to_string()))?;
+        let decoded = Self::decode(data)?;
 
-        if decoded.len() < 12 {
+        match Envelope::parse(&decoded) {
+            Some(Ok(envelope)) => self.decrypt_envelope(&envelope, binding).or_else(|e| {
+                if matches!(e, NoterError::BindingMismatch(_)) {
+                    return Err(e);
+                }
+                // A legacy nonce can start with the magic bytes by chance.
+                self.decrypt_legacy(&decoded).map_err(|_| e)
+            }),
+            Some(Err(e)) => self.decrypt_legacy(&decoded).map_err(|_| e),
+            None => self.decrypt_legacy(&decoded),
+        }
+        .map(Zeroizing::new)
+    }
+
+    fn decrypt_envelope(&self, envelope: &Envelope, binding: Option<Binding>) -> Result<Vec<u8>> {
+        if envelope.kdf != self.kdf {
+            return Err(NoterError::Encryption(format!(
+                "Data was encrypted with a {:?}-derived key, but the vault uses {:?}",
+                envelope.kdf, self.kdf
+            )));
+        }
+
+        let mut aad = envelope.header.to_vec();
+        if let Some(bound_id) = envelope.bound_id {
+            let binding = binding.ok_or_else(|| {
+                NoterError::Encryption("Data is bound to a note but no binding was given".to_string())
+            })?;
+            if bound_id != binding.id.as_bytes() {
+                return Err(NoterError::BindingMismatch(format!(
+                    "data belongs to note {}, expected {}",
+                    String::from_utf8_lossy(bound_id),
+                    binding.id
+                )));
+            }
+            aad.extend_from_slice(binding.context.as_bytes());
+        }
+
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(envelope.nonce),
+                Payload {
+                    msg: envelope.ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|e| NoterError::Encryption(e.to_string()))
+    }
+
+    /// Headerless `nonce || ciphertext` written before the envelope existed.
+    fn decrypt_legacy(&self, decoded: &[u8]) -> Result<Vec<u8>> {
+        if decoded.len() < NONCE_LEN {
             return Err(NoterError::Encryption("Invalid encrypted data".to_string()));
         }
 
-        let (nonce_bytes, ciphertext) = decoded.split_at(12);
+        let (nonce_bytes, ciphertext) = decoded.split_at(NONCE_LEN);
         let nonce = Nonce::from_slice(nonce_bytes);
 
         self.cipher
@@ -54,3 +368,129 @@ impl Crypto {
             .map_err(|e| NoterError::Encryption(e.to_string()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+    const NOTE: Binding = Binding {
+        id: "note-a",
+        context: "a.md",
+    };
+
+    fn crypto() -> Crypto {
+        Crypto::from_key_bytes(&KEY, Kdf::Sha256)
+    }
+
+    fn bytes(sealed: &str) -> Vec<u8> {
+        BASE64.decode(sealed).unwrap()
+    }
+
+    fn seal(bytes: &[u8]) -> String {
+        BASE64.encode(bytes)
+    }
+
+    #[test]
+    fn round_trip() {
+        let crypto = crypto();
+        let sealed = crypto.encrypt(b"hello").unwrap();
+        assert_eq!(crypto.decrypt(&sealed).unwrap().as_slice(), b"hello");
+
+        let sealed = crypto.encrypt_bound(b"hello", NOTE).unwrap();
+        assert_eq!(crypto.decrypt_bound(&sealed, NOTE).unwrap().as_slice(), b"hello");
+    }
+
+    #[test]
+    fn header_layout() {
+        let data = bytes(&crypto().encrypt_bound(b"x", NOTE).unwrap());
+        let envelope = Envelope::parse(&data).unwrap().unwrap();
+        assert_eq!(&data[..4], MAGIC);
+        assert_eq!(data[4], FORMAT_BOUND);
+        assert_eq!(envelope.kdf, Kdf::Sha256);
+        assert_eq!(envelope.bound_id, Some(NOTE.id.as_bytes()));
+        assert_eq!(envelope.header.len(), HEADER_LEN + 1 + NOTE.id.len());
+    }
+
+    #[test]
+    fn data_without_magic_is_not_an_envelope() {
+        assert!(Envelope::parse(b"\x00\x01\x02 not an envelope").is_none());
+    }
+
+    #[test]
+    fn truncated_header() {
+        assert!(matches!(Envelope::parse(b"NTRS\x02\x01"), Some(Err(_))));
+
+        let data = bytes(&crypto().encrypt_bound(b"x", NOTE).unwrap());
+        // Cut inside the bound id.
+        assert!(matches!(Envelope::parse(&data[..HEADER_LEN + 3]), Some(Err(_))));
+        assert!(crypto().decrypt_bound(&seal(&data[..HEADER_LEN + 3]), NOTE).is_err());
+    }
+
+    #[test]
+    fn unknown_version_kdf_and_cipher() {
+        let data = bytes(&crypto().encrypt_bound(b"x", NOTE).unwrap());
+        for (offset, message) in [(4, "version"), (5, "KDF"), (6, "cipher")] {
+            let mut bad = data.clone();
+            bad[offset] = 99;
+            match Envelope::parse(&bad) {
+                Some(Err(NoterError::Encryption(e))) => assert!(e.contains(message), "{}", e),
+                _ => panic!("byte {} = 99 should not parse", offset),
+            }
+            assert!(crypto().decrypt_bound(&seal(&bad), NOTE).is_err());
+        }
+    }
+
+    #[test]
+    fn wrong_binding() {
+        let crypto = crypto();
+        let sealed = crypto.encrypt_bound(b"x", NOTE).unwrap();
+
+        let other_note = Binding { id: "note-b", ..NOTE };
+        assert!(matches!(
+            crypto.decrypt_bound(&sealed, other_note),
+            Err(NoterError::BindingMismatch(_))
+        ));
+        let other_context = Binding { context: "b.md", ..NOTE };
+        assert!(matches!(
+            crypto.decrypt_bound(&sealed, other_context),
+            Err(NoterError::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn unbound_data_is_rejected_where_a_binding_is_required() {
+        let crypto = crypto();
+        let unbound = crypto.encrypt(b"x").unwrap();
+        assert!(crypto.decrypt_bound(&unbound, NOTE).is_err());
+        assert_eq!(crypto.decrypt_for_upgrade(&unbound, NOTE).unwrap().as_slice(), b"x");
+    }
+
+    #[test]
+    fn tampered_header_fails_authentication() {
+        let data = bytes(&crypto().encrypt_bound(b"x", NOTE).unwrap());
+
+        // Rewrite the bound id and present the matching binding: the id
+        // check passes, but the header no longer matches the tag.
+        let mut renamed = data.clone();
+        renamed[HEADER_LEN + 1 + NOTE.id.len() - 1] = b'b';
+        let binding = Binding { id: "note-b", ..NOTE };
+        assert!(matches!(
+            crypto().decrypt_bound(&seal(&renamed), binding),
+            Err(NoterError::Encryption(_))
+        ));
+
+        // Claim a different KDF to a cipher that holds the same key.
+        let mut relabelled = data.clone();
+        relabelled[5] = Kdf::Argon2id as u8;
+        let argon = Crypto::from_key_bytes(&KEY, Kdf::Argon2id);
+        assert!(argon.decrypt_bound(&seal(&relabelled), NOTE).is_err());
+    }
+
+    #[test]
+    fn wrong_key() {
+        let sealed = crypto().encrypt_bound(b"x", NOTE).unwrap();
+        let other = Crypto::from_key_bytes(&[8; 32], Kdf::Sha256);
+        assert!(other.decrypt_bound(&sealed, NOTE).is_err());
+    }
+}
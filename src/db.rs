@@ -1,124 +1,486 @@
-use crate::error::Result;
+use crate::error::{NoterError, Result};
+use crate::migrations::{self, Migration};
 use chrono::{DateTime, Local};
-use rusqlite::{params, Connection};
-use std::path::PathBuf;
+use log::info;
+use rusqlite::{params, Connection, DatabaseName, Row};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+const NOTE_COLUMNS: &str = "id, uuid, title, filename, created_at, updated_at";
 
 pub struct Database {
     conn: Connection,
+    path: PathBuf,
 }
 
-#[derive(Debug)]
+/// Outcome of [`Database::migrate`].
+pub struct MigrationReport {
+    pub from: i64,
+    pub applied: Vec<&'static Migration>,
+    pub backup: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct NoteRecord {
     pub id: i64,
-    pub title: String,
+    /// Stable identity that, unlike `id`, survives moving the note between vaults.
+    pub uuid: String,
+    /// Plaintext title (decrypted if metadata is private), wiped on drop.
+    pub title: Zeroizing<String>,
     pub filename: String,
     pub created_at: DateTime<Local>,
     pub updated_at: DateTime<Local>,
 }
 
+/// Encrypted database values re-encrypted under a new key, written in the
+/// same transaction as the key change.
+#[derive(Debug, Default)]
+pub struct RekeyedColumns {
+    pub titles: Vec<(i64, String)>,
+    pub tags: Vec<(i64, String)>,
+    pub revisions: Vec<(i64, String)>,
+    pub links: Vec<(i64, String)>,
+}
+
+/// A saved version of a note. `content` is encrypted as stored.
+#[derive(Debug)]
+pub struct RevisionRecord {
+    pub id: i64,
+    pub note_id: i64,
+    pub rev: i64,
+    pub created_at: DateTime<Local>,
+    pub content: String,
+}
+
 impl Database {
+    /// Opens the database and brings its schema up to date.
     pub fn new(db_path: PathBuf) -> Result<Self> {
+        let db = Self::open(db_path)?;
+        db.migrate()?;
+        Ok(db)
+    }
+
+    /// Opens the database without running migrations.
+    pub fn open(db_path: PathBuf) -> Result<Self> {
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)?;
         }
 
-        let conn = Connection::open(db_path)?;
-        
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS notes (
-                id INTEGER PRIMARY KEY,
-                title TEXT NOT NULL,
-                filename TEXT NOT NULL UNIQUE,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )",
+        let conn = Connection::open(&db_path)?;
+        Ok(Database {
+            conn,
+            path: db_path,
+        })
+    }
+
+    pub fn schema_version(&self) -> Result<i64> {
+        migrations::current_version(&self.conn)
+    }
+
+    pub fn pending_migrations(&self) -> Result<Vec<&'static Migration>> {
+        let current = self.schema_version()?;
+        if current > migrations::latest_version() {
+            return Err(NoterError::Database(format!(
+                "Database schema version {} is newer than this version of noters supports ({})",
+                current,
+                migrations::latest_version()
+            )));
+        }
+        migrations::pending(&self.conn)
+    }
+
+    /// Applies pending migrations in order. A database that already holds
+    /// tables is backed up next to itself before the first one runs.
+    pub fn migrate(&self) -> Result<MigrationReport> {
+        let from = self.schema_version()?;
+        let pending = self.pending_migrations()?;
+        let mut report = MigrationReport {
+            from,
+            applied: Vec::new(),
+            backup: None,
+        };
+        if pending.is_empty() {
+            return Ok(report);
+        }
+
+        let has_tables = self
+            .conn
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table'")?
+            .exists([])?;
+        if has_tables {
+            let backup = self.backup_path(from);
+            self.backup_to(&backup)?;
+            info!("Backed up database to {}", backup.display());
+            report.backup = Some(backup);
+        }
+
+        for migration in pending {
+            migrations::apply(&self.conn, migration)?;
+            info!(
+                "Applied migration {}: {}",
+                migration.version, migration.description
+            );
+            report.applied.push(migration);
+        }
+        Ok(report)
+    }
+
+    fn backup_path(&self, version: i64) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(
+            ".v{}-{}.bak",
+            version,
+            Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        self.path.with_file_name(name)
+    }
+
+    /// Copies the database to `path` with SQLite's online backup API.
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        self.conn.backup(DatabaseName::Main, path, None)?;
+        Ok(())
+    }
+
+    pub fn get_meta(&self, key: &str) -> Result<Option<String>> {
+        let mut stmt = self.conn.prepare("SELECT value FROM vault_meta WHERE key = ?1")?;
+        let mut values = stmt
+            .query_map([key], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(values.pop())
+    }
+
+    /// Writes several meta entries in a single transaction.
+    pub fn set_meta(&self, entries: &[(&str, &str)]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (key, value) in entries {
+            tx.execute(
+                "INSERT INTO vault_meta (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Atomically swaps the key-derivation entries for `entries` and writes
+    /// the re-encrypted columns. This is the commit point of a key rotation.
+    pub fn commit_rekey(&self, entries: &[(&str, &str)], columns: &RekeyedColumns) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM vault_meta WHERE key = 'key_check' OR key LIKE 'kdf%'",
             [],
         )?;
+        for (key, value) in entries {
+            tx.execute(
+                "INSERT INTO vault_meta (key, value) VALUES (?1, ?2)",
+                params![key, value],
+            )?;
+        }
+        for (id, title) in &columns.titles {
+            tx.execute("UPDATE notes SET title = ?1 WHERE id = ?2", params![title, id])?;
+        }
+        for (id, name) in &columns.tags {
+            tx.execute("UPDATE tags SET name = ?1 WHERE id = ?2", params![name, id])?;
+        }
+        for (id, content) in &columns.revisions {
+            tx.execute(
+                "UPDATE revisions SET content = ?1 WHERE id = ?2",
+                params![content, id],
+            )?;
+        }
+        for (id, link) in &columns.links {
+            tx.execute("UPDATE links SET link = ?1 WHERE id = ?2", params![link, id])?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
 
-        Ok(Database { conn })
+    /// Rewrites titles and filenames of several notes, tag names, links, and
+    /// sets `entries` in one transaction.
+    pub fn rewrite_notes(
+        &self,
+        notes: &[(i64, String, String)],
+        tags: &[(i64, String)],
+        links: &[(i64, String)],
+        entries: &[(&str, &str)],
+    ) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, title, filename) in notes {
+            tx.execute(
+                "UPDATE notes SET title = ?1, filename = ?2 WHERE id = ?3",
+                params![title, filename, id],
+            )?;
+        }
+        for (id, name) in tags {
+            tx.execute("UPDATE tags SET name = ?1 WHERE id = ?2", params![name, id])?;
+        }
+        for (id, link) in links {
+            tx.execute("UPDATE links SET link = ?1 WHERE id = ?2", params![link, id])?;
+        }
+        for (key, value) in entries {
+            tx.execute(
+                "INSERT INTO vault_meta (key, value) VALUES (?1, ?2)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                params![key, value],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
     }
 
-    pub fn insert_note(&self, title: &str, filename: &str) -> Result<i64> {
+    pub fn count_notes(&self) -> Result<i64> {
+        let count = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM notes", [], |row| row.get(0))?;
+        Ok(count)
+    }
+
+    pub fn insert_note(&self, uuid: &str, title: &str, filename: &str) -> Result<i64> {
         let now = Local::now();
+        self.insert_note_at(uuid, title, filename, now, now)
+    }
+
+    /// Inserts a note with existing timestamps, e.g. one being imported.
+    pub fn insert_note_at(
+        &self,
+        uuid: &str,
+        title: &str,
+        filename: &str,
+        created_at: DateTime<Local>,
+        updated_at: DateTime<Local>,
+    ) -> Result<i64> {
         self.conn.execute(
-            "INSERT INTO notes (title, filename, created_at, updated_at) VALUES (?1, ?2, ?3, ?4)",
-            params![title, filename, now.to_rfc3339(), now.to_rfc3339()],
+            "INSERT INTO notes (uuid, title, filename, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![uuid, title, filename, created_at.to_rfc3339(), updated_at.to_rfc3339()],
         )?;
         Ok(self.conn.last_insert_rowid())
     }
 
     pub fn get_all_notes(&self) -> Result<Vec<NoteRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, title, filename, created_at, updated_at FROM notes ORDER BY created_at DESC",
-        )?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM notes ORDER BY created_at DESC",
+            NOTE_COLUMNS
+        ))?;
         let notes = stmt
-            .query_map([], |row| {
-                Ok(NoteRecord {
-                    id: row.get(0)?,
-                    title: row.get(1)?,
-                    filename: row.get(2)?,
-                    created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
-                        .unwrap()
-                        .with_timezone(&Local),
-                    updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                        .unwrap()
-                        .with_timezone(&Local),
-                })
-            })?
+            .query_map([], Self::note_from_row)?
             .collect::<std::result::Result<Vec<_>, _>>()?;
         Ok(notes)
     }
 
     pub fn search_notes(&self, query: &str) -> Result<Vec<NoteRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, title, filename, created_at, updated_at 
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} 
              FROM notes 
              WHERE title LIKE ?1 OR filename LIKE ?1
              ORDER BY created_at DESC",
-        )?;
+            NOTE_COLUMNS
+        ))?;
         let search_pattern = format!("%{}%", query);
         let notes = stmt
-            .query_map([search_pattern], |row| {
-                Ok(NoteRecord {
-                    id: row.get(0)?,
-                    title: row.get(1)?,
-                    filename: row.get(2)?,
-                    created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
-                        .unwrap()
-                        .with_timezone(&Local),
-                    updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                        .unwrap()
-                        .with_timezone(&Local),
-                })
-            })?
+            .query_map([search_pattern], Self::note_from_row)?
             .collect::<std::result::Result<Vec<_>, _>>()?;
         Ok(notes)
     }
 
     pub fn get_note(&self, id: i64) -> Result<Option<NoteRecord>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, title, filename, created_at, updated_at FROM notes WHERE id = ?1",
-        )?;
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {} FROM notes WHERE id = ?1", NOTE_COLUMNS))?;
         let mut notes = stmt
-            .query_map([id], |row| {
-                Ok(NoteRecord {
-                    id: row.get(0)?,
-                    title: row.get(1)?,
-                    filename: row.get(2)?,
-                    created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
-                        .unwrap()
-                        .with_timezone(&Local),
-                    updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
-                        .unwrap()
-                        .with_timezone(&Local),
-                })
-            })?
+            .query_map([id], Self::note_from_row)?
             .collect::<std::result::Result<Vec<_>, _>>()?;
         Ok(notes.pop())
     }
 
+    pub fn get_note_by_uuid(&self, uuid: &str) -> Result<Option<NoteRecord>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {} FROM notes WHERE uuid = ?1", NOTE_COLUMNS))?;
+        let mut notes = stmt
+            .query_map([uuid], Self::note_from_row)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(notes.pop())
+    }
+
+    fn note_from_row(row: &Row) -> rusqlite::Result<NoteRecord> {
+        Ok(NoteRecord {
+            id: row.get(0)?,
+            uuid: row.get(1)?,
+            title: Zeroizing::new(row.get(2)?),
+            filename: row.get(3)?,
+            created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(4)?)
+                .unwrap()
+                .with_timezone(&Local),
+            updated_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(5)?)
+                .unwrap()
+                .with_timezone(&Local),
+        })
+    }
+
+    pub fn update_title(&self, id: i64, title: &str) -> Result<()> {
+        self.conn
+            .execute("UPDATE notes SET title = ?1 WHERE id = ?2", params![title, id])?;
+        Ok(())
+    }
+
+    pub fn set_updated_at(&self, id: i64, updated_at: DateTime<Local>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE notes SET updated_at = ?1 WHERE id = ?2",
+            params![updated_at.to_rfc3339(), id],
+        )?;
+        Ok(())
+    }
+
     pub fn delete_note(&self, id: i64) -> Result<bool> {
-        let rows_affected = self.conn.execute("DELETE FROM notes WHERE id = ?1", [id])?;
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM note_tags WHERE note_id = ?1", [id])?;
+        Self::delete_unused_tags(&tx)?;
+        tx.execute("DELETE FROM revisions WHERE note_id = ?1", [id])?;
+        tx.execute("DELETE FROM links WHERE note_id = ?1", [id])?;
+        let rows_affected = tx.execute("DELETE FROM notes WHERE id = ?1", [id])?;
+        tx.commit()?;
         Ok(rows_affected > 0)
     }
+
+    /// Stores a new revision of a note and returns its number.
+    pub fn insert_revision(&self, note_id: i64, content: &str) -> Result<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        let rev: i64 = tx.query_row(
+            "SELECT COALESCE(MAX(rev), 0) + 1 FROM revisions WHERE note_id = ?1",
+            [note_id],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "INSERT INTO revisions (note_id, rev, created_at, content) VALUES (?1, ?2, ?3, ?4)",
+            params![note_id, rev, Local::now().to_rfc3339(), content],
+        )?;
+        tx.commit()?;
+        Ok(rev)
+    }
+
+    /// Revisions of one note, oldest first, or of every note when `note_id`
+    /// is `None`.
+    pub fn get_revisions(&self, note_id: Option<i64>) -> Result<Vec<RevisionRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, note_id, rev, created_at, content FROM revisions
+             WHERE ?1 IS NULL OR note_id = ?1
+             ORDER BY note_id, rev",
+        )?;
+        let revisions = stmt
+            .query_map([note_id], Self::revision_from_row)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(revisions)
+    }
+
+    pub fn get_revision(&self, note_id: i64, rev: i64) -> Result<Option<RevisionRecord>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, note_id, rev, created_at, content FROM revisions
+             WHERE note_id = ?1 AND rev = ?2",
+        )?;
+        let mut revisions = stmt
+            .query_map([note_id, rev], Self::revision_from_row)?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(revisions.pop())
+    }
+
+    fn revision_from_row(row: &Row) -> rusqlite::Result<RevisionRecord> {
+        Ok(RevisionRecord {
+            id: row.get(0)?,
+            note_id: row.get(1)?,
+            rev: row.get(2)?,
+            created_at: DateTime::parse_from_rfc3339(&row.get::<_, String>(3)?)
+                .unwrap()
+                .with_timezone(&Local),
+            content: row.get(4)?,
+        })
+    }
+
+    /// All tags as `(id, stored name)`.
+    pub fn get_tags(&self) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare("SELECT id, name FROM tags ORDER BY id")?;
+        let tags = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+
+    /// All tags as `(id, stored name, number of notes)`.
+    pub fn get_tag_counts(&self) -> Result<Vec<(i64, String, i64)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tags.id, tags.name, COUNT(note_tags.note_id)
+             FROM tags
+             LEFT JOIN note_tags ON note_tags.tag_id = tags.id
+             GROUP BY tags.id
+             ORDER BY tags.id",
+        )?;
+        let tags = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(tags)
+    }
+
+    pub fn insert_tag(&self, name: &str) -> Result<i64> {
+        self.conn.execute("INSERT INTO tags (name) VALUES (?1)", [name])?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn get_note_ids_with_tag(&self, tag_id: i64) -> Result<Vec<i64>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT note_id FROM note_tags WHERE tag_id = ?1")?;
+        let ids = stmt
+            .query_map([tag_id], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(ids)
+    }
+
+    /// Replaces the tags of a note and drops tags no note uses any more.
+    pub fn set_note_tags(&self, note_id: i64, tag_ids: &[i64]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM note_tags WHERE note_id = ?1", [note_id])?;
+        for tag_id in tag_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
+                [note_id, *tag_id],
+            )?;
+        }
+        Self::delete_unused_tags(&tx)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every link as `(id, note id, stored link)`, in note order.
+    pub fn get_links(&self) -> Result<Vec<(i64, i64, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, note_id, link FROM links ORDER BY note_id, id")?;
+        let links = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(links)
+    }
+
+    /// Replaces the links of a note.
+    pub fn set_note_links(&self, note_id: i64, links: &[String]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM links WHERE note_id = ?1", [note_id])?;
+        for link in links {
+            tx.execute(
+                "INSERT INTO links (note_id, link) VALUES (?1, ?2)",
+                params![note_id, link],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete_unused_tags(conn: &Connection) -> Result<()> {
+        conn.execute(
+            "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM note_tags)",
+            [],
+        )?;
+        Ok(())
+    }
 }
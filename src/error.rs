@@ -17,12 +17,33 @@ pub enum NoterError {
     #[error("Encryption error: {0}")]
     Encryption(String),
 
+    #[error("Note binding mismatch: {0}")]
+    BindingMismatch(String),
+
+    #[error("Incorrect passphrase")]
+    InvalidPassphrase,
+
+    #[error("Cannot prompt for a passphrase without a terminal; {0}")]
+    PassphraseUnavailable(String),
+
     #[error("Invalid title: {0}")]
     InvalidTitle(String),
 
     #[error("Note not found: {0}")]
     NoteNotFound(i64),
 
+    #[error("No note matches '{0}'")]
+    NoMatchingNote(String),
+
+    #[error("'{0}' matches several notes: {1}. Use an ID or a more specific query")]
+    AmbiguousNote(String, String),
+
+    #[error("'{0}' is not an exact title; it best matches {1}. Use its ID or exact title")]
+    InexactNote(String, String),
+
+    #[error("Revision {1} of note {0} not found")]
+    RevisionNotFound(i64, i64),
+
     #[error("Home directory not found")]
     HomeDirNotFound,
 
@@ -39,6 +60,46 @@ pub enum NoterError {
     InvalidInput(String),
 }
 
+impl NoterError {
+    /// A stable identifier for scripts consuming `--format json` output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            NoterError::Io(_) => "io",
+            NoterError::Database(_) => "database",
+            NoterError::Config(_) => "config",
+            NoterError::Encryption(_) => "encryption",
+            NoterError::BindingMismatch(_) => "binding_mismatch",
+            NoterError::InvalidPassphrase => "invalid_passphrase",
+            NoterError::PassphraseUnavailable(_) => "passphrase_unavailable",
+            NoterError::InvalidTitle(_) => "invalid_title",
+            NoterError::NoteNotFound(_) | NoterError::NoMatchingNote(_) => "note_not_found",
+            NoterError::AmbiguousNote(..) | NoterError::InexactNote(..) => "ambiguous_note",
+            NoterError::RevisionNotFound(..) => "revision_not_found",
+            NoterError::HomeDirNotFound => "home_dir_not_found",
+            NoterError::EditorNotFound => "editor_not_found",
+            NoterError::EditorError(_) => "editor_error",
+            NoterError::ExportError(_) => "export_error",
+            NoterError::InvalidInput(_) => "invalid_input",
+        }
+    }
+
+    /// Process exit status for this error. 2 is shared with command-line
+    /// usage errors reported by the argument parser.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            NoterError::InvalidInput(_) | NoterError::InvalidTitle(_) | NoterError::AmbiguousNote(..) | NoterError::InexactNote(..) => 2,
+            NoterError::NoteNotFound(_) | NoterError::NoMatchingNote(_) | NoterError::RevisionNotFound(..) => 3,
+            NoterError::InvalidPassphrase | NoterError::PassphraseUnavailable(_) => 4,
+            NoterError::Encryption(_) | NoterError::BindingMismatch(_) => 5,
+            NoterError::Database(_) => 6,
+            NoterError::Config(_) | NoterError::HomeDirNotFound => 7,
+            NoterError::Io(_) => 8,
+            NoterError::EditorNotFound | NoterError::EditorError(_) => 9,
+            NoterError::ExportError(_) => 10,
+        }
+    }
+}
+
 impl From<io::Error> for NoterError {
     fn from(error: io::Error) -> Self {
         NoterError::Io(error)
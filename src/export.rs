@@ -0,0 +1,355 @@
+//! Rendering decrypted notes into the single-file export formats and
+//! writing export archives.
+
+use crate::error::{NoterError, Result};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use flate2::{write::GzEncoder, Compression};
+use pulldown_cmark::{html, Options, Parser};
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use zeroize::Zeroizing;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// One decrypted file per note.
+    #[default]
+    Md,
+    /// One JSON document with every note and its metadata.
+    Json,
+    /// One browsable HTML page with an index.
+    Html,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Ok(Self::Md),
+            "json" => Ok(Self::Json),
+            "html" => Ok(Self::Html),
+            other => Err(format!("unknown export format '{}' (expected md, json or html)", other)),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Md => "md",
+            Self::Json => "json",
+            Self::Html => "html",
+        })
+    }
+}
+
+/// What to do when an export would write over an existing file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    #[default]
+    Overwrite,
+    Skip,
+    /// Write next to the existing file with a distinguishing suffix.
+    Rename,
+}
+
+impl CollisionPolicy {
+    /// Where to write `path` under this policy, or `None` to skip it.
+    /// Renamed files get `-{suffix}` and, if that is taken too, a counter.
+    pub fn resolve(self, path: PathBuf, suffix: &str) -> Option<PathBuf> {
+        if !path.exists() {
+            return Some(path);
+        }
+        match self {
+            Self::Overwrite => Some(path),
+            Self::Skip => None,
+            Self::Rename => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                let ext = path
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_default();
+                let mut candidate = path.with_file_name(format!("{}-{}{}", stem, suffix, ext));
+                let mut n = 2;
+                while candidate.exists() {
+                    candidate = path.with_file_name(format!("{}-{}-{}{}", stem, suffix, n, ext));
+                    n += 1;
+                }
+                Some(candidate)
+            }
+        }
+    }
+}
+
+/// Counts of an export run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ExportReport {
+    pub exported: usize,
+    /// Notes not written because a file already existed under
+    /// [`CollisionPolicy::Skip`].
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl ExportReport {
+    pub fn total(&self) -> usize {
+        self.exported + self.skipped + self.failed
+    }
+}
+
+/// File stems for exporting `titles`, given as `(note id, sanitized title)`.
+/// Notes sharing a title are told apart deterministically: the one with the
+/// lowest ID keeps the bare title and the others get `-{id}` appended, so
+/// the same selection always exports to the same names.
+pub fn unique_stems(titles: &[(i64, String)]) -> Vec<String> {
+    let mut order: Vec<usize> = (0..titles.len()).collect();
+    order.sort_by_key(|&i| titles[i].0);
+
+    let mut stems = vec![String::new(); titles.len()];
+    let mut used: Vec<String> = Vec::with_capacity(titles.len());
+    for i in order {
+        let (id, stem) = &titles[i];
+        let taken = |candidate: &str| used.iter().any(|u| u.eq_ignore_ascii_case(candidate));
+        let mut candidate = stem.clone();
+        if taken(&candidate) {
+            candidate = format!("{}-{}", stem, id);
+            let mut n = 2;
+            while taken(&candidate) {
+                candidate = format!("{}-{}-{}", stem, id, n);
+                n += 1;
+            }
+        }
+        used.push(candidate.clone());
+        stems[i] = candidate;
+    }
+    stems
+}
+
+/// A decrypted note with the metadata written to JSON and HTML exports.
+#[derive(Debug, Serialize)]
+pub struct ExportedNote {
+    pub id: i64,
+    pub uuid: String,
+    pub title: Zeroizing<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub tags: Vec<String>,
+    /// The note without its front matter.
+    pub body: Zeroizing<String>,
+}
+
+pub fn to_json(notes: &[ExportedNote]) -> Result<String> {
+    #[derive(Serialize)]
+    struct Document<'a> {
+        exported_at: String,
+        notes: &'a [ExportedNote],
+    }
+
+    serde_json::to_string_pretty(&Document {
+        exported_at: chrono::Local::now().to_rfc3339(),
+        notes,
+    })
+    .map_err(|e| NoterError::ExportError(e.to_string()))
+}
+
+/// Renders every note's Markdown into one page. The index links to each
+/// note's section and every section links back to the index.
+pub fn to_html(notes: &[ExportedNote]) -> String {
+    let mut out = String::from(HTML_HEAD);
+
+    out.push_str("<nav id=\"index\">\n<h1>Notes</h1>\n<ul>\n");
+    for note in notes {
+        out.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a> <time>{}</time></li>\n",
+            anchor(note),
+            escape(&note.title),
+            display_date(&note.created_at)
+        ));
+    }
+    out.push_str("</ul>\n</nav>\n");
+
+    for note in notes {
+        out.push_str(&format!(
+            "<article id=\"{}\">\n<h2>{}</h2>\n<p class=\"meta\">Created {} · Updated {}",
+            anchor(note),
+            escape(&note.title),
+            display_date(&note.created_at),
+            display_date(&note.updated_at)
+        ));
+        for tag in &note.tags {
+            out.push_str(&format!(" <span class=\"tag\">{}</span>", escape(tag)));
+        }
+        out.push_str("</p>\n");
+        html::push_html(&mut out, Parser::new_ext(&note.body, Options::all()));
+        out.push_str("<p><a href=\"#index\">Back to index</a></p>\n</article>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// One note in an archive's `manifest.json`.
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+    pub id: i64,
+    pub uuid: String,
+    pub title: Zeroizing<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Path of the note inside the archive.
+    pub path: String,
+    /// SHA-256 of the exported file, hex encoded.
+    pub sha256: String,
+}
+
+/// Name of the manifest written at the root of every export archive.
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// A zip or tar export being written. Entries are streamed to disk as they
+/// are added; the manifest is written by [`ExportArchive::finish`].
+pub enum ExportArchive {
+    Zip(Box<ZipWriter<File>>),
+    Tar(tar::Builder<File>),
+    TarGz(Box<tar::Builder<GzEncoder<File>>>),
+}
+
+impl ExportArchive {
+    /// Picks the archive type from the extension of `path`: `.zip`, `.tar`,
+    /// `.tar.gz` or `.tgz`.
+    pub fn create(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".zip") {
+            Ok(Self::Zip(Box::new(ZipWriter::new(File::create(path)?))))
+        } else if name.ends_with(".tar") {
+            Ok(Self::Tar(tar::Builder::new(File::create(path)?)))
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            let encoder = GzEncoder::new(File::create(path)?, Compression::default());
+            Ok(Self::TarGz(Box::new(tar::Builder::new(encoder))))
+        } else {
+            Err(NoterError::InvalidInput(format!(
+                "Unsupported archive type for {} (expected .zip, .tar, .tar.gz or .tgz)",
+                path.display()
+            )))
+        }
+    }
+
+    pub fn add(&mut self, name: &str, contents: &[u8], modified: DateTime<Local>) -> Result<()> {
+        match self {
+            Self::Zip(zip) => {
+                let mut options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Deflated)
+                    .unix_permissions(0o600);
+                if let Ok(time) = zip::DateTime::from_date_and_time(
+                    modified.year() as u16,
+                    modified.month() as u8,
+                    modified.day() as u8,
+                    modified.hour() as u8,
+                    modified.minute() as u8,
+                    modified.second() as u8,
+                ) {
+                    options = options.last_modified_time(time);
+                }
+                zip.start_file(name, options).map_err(archive_error)?;
+                zip.write_all(contents)?;
+            }
+            Self::Tar(tar) => append_tar(tar, name, contents, modified)?,
+            Self::TarGz(tar) => append_tar(tar, name, contents, modified)?,
+        }
+        Ok(())
+    }
+
+    /// Writes `manifest.json` and closes the archive.
+    pub fn finish(mut self, entries: &[ManifestEntry]) -> Result<()> {
+        #[derive(Serialize)]
+        struct Manifest<'a> {
+            exported_at: String,
+            notes: &'a [ManifestEntry],
+        }
+
+        let now = Local::now();
+        let manifest = serde_json::to_string_pretty(&Manifest {
+            exported_at: now.to_rfc3339(),
+            notes: entries,
+        })
+        .map_err(|e| NoterError::ExportError(e.to_string()))?;
+        self.add(MANIFEST_NAME, manifest.as_bytes(), now)?;
+
+        match self {
+            Self::Zip(zip) => {
+                zip.finish().map_err(archive_error)?.sync_all()?;
+            }
+            Self::Tar(tar) => tar.into_inner()?.sync_all()?,
+            Self::TarGz(tar) => tar.into_inner()?.finish()?.sync_all()?,
+        }
+        Ok(())
+    }
+}
+
+fn append_tar<W: Write>(
+    tar: &mut tar::Builder<W>,
+    name: &str,
+    contents: &[u8],
+    modified: DateTime<Local>,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(modified.timestamp().max(0) as u64);
+    header.set_cksum();
+    tar.append_data(&mut header, name, contents)?;
+    Ok(())
+}
+
+fn archive_error(e: zip::result::ZipError) -> NoterError {
+    NoterError::ExportError(e.to_string())
+}
+
+fn anchor(note: &ExportedNote) -> String {
+    format!("note-{}", note.id)
+}
+
+fn display_date(rfc3339: &str) -> String {
+    match chrono::DateTime::parse_from_rfc3339(rfc3339) {
+        Ok(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+        Err(_) => escape(rfc3339),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Notes</title>
+<style>
+body { max-width: 48rem; margin: 2rem auto; padding: 0 1rem; font-family: sans-serif; line-height: 1.5; }
+article { border-top: 1px solid #ddd; margin-top: 2rem; }
+.meta, time { color: #777; font-size: 0.9em; }
+.tag { background: #eef; border-radius: 3px; padding: 0 0.3em; }
+pre { background: #f6f6f6; padding: 0.5em; overflow-x: auto; }
+</style>
+</head>
+<body>
+"#;
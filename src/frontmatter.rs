@@ -0,0 +1,286 @@
+//! Parsing and writing the YAML front matter block at the top of a note.
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_yaml_ng::{Mapping, Value};
+
+/// The format `noters` writes dates in.
+pub const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Typed view of a note's front matter. Unknown keys are kept in `extra`
+/// so that rewriting a note never drops fields added by hand or other tools.
+/// Dates are kept as written and parsed on access for the same reason.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NoteMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "scalar")]
+    pub title: Option<String>,
+    /// The note's stable identity, matching the `uuid` column of `notes`.
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "scalar")]
+    pub uuid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "scalar")]
+    pub date: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "scalar")]
+    pub updated: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty", deserialize_with = "string_list")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty", deserialize_with = "string_list")]
+    pub aliases: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Mapping,
+}
+
+impl NoteMetadata {
+    pub fn new(title: &str, created: DateTime<Local>) -> Self {
+        Self {
+            title: Some(title.to_string()),
+            date: Some(created.format(DATE_FORMAT).to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// Parses a front matter block (without the `---` fences). Blocks that
+    /// are not valid YAML, such as those written by older versions for
+    /// titles containing a colon, are read line by line instead.
+    pub fn parse(front: &str) -> Self {
+        if front.trim().is_empty() {
+            return Self::default();
+        }
+        match serde_yaml_ng::from_str(front) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                log::warn!("Front matter is not valid YAML ({}), reading it leniently", e);
+                Self::parse_lenient(front)
+            }
+        }
+    }
+
+    fn parse_lenient(front: &str) -> Self {
+        let mut metadata = Self::default();
+        let mut lines = front.lines().peekable();
+        while let Some(line) = lines.next() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            let mut list = || -> Vec<String> {
+                let items: Vec<String> = if value.is_empty() {
+                    let mut items = Vec::new();
+                    while let Some(item) = lines
+                        .peek()
+                        .and_then(|l| l.trim_start().strip_prefix("- "))
+                    {
+                        items.push(item.to_string());
+                        lines.next();
+                    }
+                    items
+                } else {
+                    value
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .split(',')
+                        .map(str::to_string)
+                        .collect()
+                };
+                clean_list(items)
+            };
+
+            match key.trim() {
+                "title" => metadata.title = Some(unquote(value)),
+                "uuid" => metadata.uuid = Some(unquote(value)),
+                "date" => metadata.date = Some(unquote(value)),
+                "updated" => metadata.updated = Some(unquote(value)),
+                "tags" => metadata.tags = list(),
+                "aliases" => metadata.aliases = list(),
+                other => {
+                    metadata.extra.insert(
+                        Value::String(other.to_string()),
+                        Value::String(unquote(value)),
+                    );
+                }
+            }
+        }
+        metadata
+    }
+
+    /// Reads the metadata of a full note, or an empty set if it has no
+    /// front matter.
+    pub fn from_content(content: &str) -> Self {
+        split(content).0.map(Self::parse).unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn created(&self) -> Option<DateTime<Local>> {
+        self.date.as_deref().and_then(parse_date)
+    }
+
+    pub fn updated(&self) -> Option<DateTime<Local>> {
+        self.updated.as_deref().and_then(parse_date)
+    }
+
+    /// Serializes the metadata in front of `body`.
+    pub fn render(&self, body: &str) -> String {
+        if self.is_empty() {
+            return body.to_string();
+        }
+        let yaml = serde_yaml_ng::to_string(self).expect("front matter is always serializable");
+        format!("---\n{}---\n\n{}", yaml, body)
+    }
+}
+
+/// Splits `content` into its front matter (without the `---` fences) and the
+/// body that follows it. Fences may end in `\n` or `\r\n`.
+pub fn split(content: &str) -> (Option<&str>, &str) {
+    let Some(rest) = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    else {
+        return (None, content);
+    };
+    let mut end = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end_matches(['\r', '\n']) == "---" {
+            let body = &rest[end + line.len()..];
+            return (Some(&rest[..end]), body.trim_start_matches(['\r', '\n']));
+        }
+        end += line.len();
+    }
+    (None, content)
+}
+
+/// `content` with `uuid` set in its front matter, or `None` if it already
+/// has it. Front matter is added to notes without any.
+pub fn with_uuid(content: &str, uuid: &str) -> Option<String> {
+    let (front, body) = split(content);
+    let mut metadata = front.map(NoteMetadata::parse).unwrap_or_default();
+    if metadata.uuid.as_deref() == Some(uuid) {
+        return None;
+    }
+    metadata.uuid = Some(uuid.to_string());
+    Some(metadata.render(body))
+}
+
+/// The note body with any front matter removed.
+pub fn body(content: &str) -> &str {
+    split(content).1
+}
+
+/// Accepts the formats `noters` and common note tools write: our own
+/// `%Y-%m-%d %H:%M:%S`, RFC 3339, or a bare date.
+pub fn parse_date(value: &str) -> Option<DateTime<Local>> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Local));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, DATE_FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+    Local.from_local_datetime(&naive).earliest()
+}
+
+fn unquote(value: &str) -> String {
+    value.trim().trim_matches(['"', '\'']).to_string()
+}
+
+fn clean_list(items: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for item in items {
+        let item = unquote(&item);
+        if !item.is_empty() && !cleaned.contains(&item) {
+            cleaned.push(item);
+        }
+    }
+    cleaned
+}
+
+fn value_to_string(value: Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Reads any YAML scalar as a string, so `title: 2024` is still a title.
+fn scalar<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(value_to_string(Value::deserialize(deserializer)?))
+}
+
+/// Reads a YAML sequence or a single comma-separated scalar as a list.
+fn string_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let items = match Value::deserialize(deserializer)? {
+        Value::Sequence(items) => items.into_iter().filter_map(value_to_string).collect(),
+        other => value_to_string(other)
+            .map(|s| s.split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+    };
+    Ok(clean_list(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_lf() {
+        let (front, body) = split("---\ntitle: A\ntags: [x]\n---\n\nBody\n");
+        assert_eq!(front, Some("title: A\ntags: [x]\n"));
+        assert_eq!(body, "Body\n");
+    }
+
+    #[test]
+    fn split_crlf() {
+        let content = "---\r\ntitle: A\r\ntags: [x, y]\r\n---\r\n\r\nBody\r\n";
+        let (front, body) = split(content);
+        assert_eq!(front, Some("title: A\r\ntags: [x, y]\r\n"));
+        assert_eq!(body, "Body\r\n");
+
+        let metadata = NoteMetadata::from_content(content);
+        assert_eq!(metadata.title.as_deref(), Some("A"));
+        assert_eq!(metadata.tags, ["x", "y"]);
+    }
+
+    #[test]
+    fn split_without_front_matter() {
+        assert_eq!(split("Just text\n---\n"), (None, "Just text\n---\n"));
+        assert_eq!(split(""), (None, ""));
+    }
+
+    #[test]
+    fn split_empty_front_matter() {
+        assert_eq!(split("---\n---\nBody"), (Some(""), "Body"));
+    }
+
+    #[test]
+    fn split_unterminated_fence() {
+        let content = "---\ntitle: A\nno closing fence\n";
+        assert_eq!(split(content), (None, content));
+    }
+
+    #[test]
+    fn closing_fence_must_be_its_own_line() {
+        let content = "---\ntitle: A\n----\n";
+        assert_eq!(split(content), (None, content));
+    }
+
+    #[test]
+    fn with_uuid_keeps_one_front_matter_block() {
+        let content = "---\r\ntitle: A\r\n---\r\nBody\r\n";
+        let updated = with_uuid(content, "u-1").unwrap();
+        assert_eq!(updated.matches("---").count(), 2);
+        let metadata = NoteMetadata::from_content(&updated);
+        assert_eq!(metadata.title.as_deref(), Some("A"));
+        assert_eq!(metadata.uuid.as_deref(), Some("u-1"));
+        assert_eq!(with_uuid(&updated, "u-1"), None);
+    }
+}
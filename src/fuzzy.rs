@@ -0,0 +1,50 @@
+//! Fuzzy matching of note titles, for picking notes without their ID.
+
+/// Scores how well `query` matches `text`, or `None` if the characters of
+/// `query` (ignoring case and whitespace) do not all appear in `text` in
+/// order. Higher is better: consecutive characters and characters at the
+/// start of a word score extra, gaps between matches cost a little.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(fold)
+        .collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut last_match: Option<usize> = None;
+    for (i, &c) in text.iter().enumerate() {
+        if next == query.len() {
+            break;
+        }
+        if fold(c) != query[next] {
+            continue;
+        }
+
+        score += 1;
+        match last_match {
+            Some(last) if last + 1 == i => score += 5,
+            Some(last) => score -= (i - last - 1).min(5) as i64,
+            None => score -= i.min(5) as i64,
+        }
+        let word_start = i == 0
+            || !text[i - 1].is_alphanumeric()
+            || (text[i - 1].is_lowercase() && c.is_uppercase());
+        if word_start {
+            score += 8;
+        }
+        last_match = Some(i);
+        next += 1;
+    }
+
+    (next == query.len()).then_some(score)
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
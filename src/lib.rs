@@ -1,8 +1,20 @@
+pub mod backup;
 pub mod config;
 pub mod crypto;
 pub mod db;
 pub mod error;
+pub mod export;
+pub mod frontmatter;
+pub mod fuzzy;
+pub mod links;
+pub mod migrations;
 pub mod note;
+pub mod output;
+pub mod rekey;
+pub mod render;
+pub mod search;
+pub mod staging;
+pub mod tui;
 pub mod utils;
 
 pub use crate::config::Config;
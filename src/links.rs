@@ -0,0 +1,89 @@
+//! `[[wiki links]]` between notes.
+//!
+//! A link names its target by title (`[[Note Title]]`) or UUID
+//! (`[[uuid|alias]]`), optionally with a `#section`. Links are resolved
+//! against the vault when they are read, so renaming or deleting a note
+//! shows up as broken links rather than stale ones.
+
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikiLink {
+    /// The title or UUID linked to, with any `#section`.
+    pub target: String,
+    /// The text after the `|`, if any.
+    pub alias: Option<String>,
+}
+
+impl WikiLink {
+    /// Parses the text between the brackets, e.g. `Title|alias`.
+    pub fn parse(inner: &str) -> Self {
+        match inner.split_once('|') {
+            Some((target, alias)) => Self {
+                target: target.trim().to_string(),
+                alias: Some(alias.trim().to_string()),
+            },
+            None => Self {
+                target: inner.trim().to_string(),
+                alias: None,
+            },
+        }
+    }
+
+    /// The target without its `#section`.
+    pub fn note(&self) -> &str {
+        match self.target.split_once('#') {
+            Some((note, _)) => note.trim(),
+            None => &self.target,
+        }
+    }
+}
+
+/// Writes the text between the brackets, the form [`WikiLink::parse`] reads.
+impl fmt::Display for WikiLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.alias {
+            Some(alias) => write!(f, "{}|{}", self.target, alias),
+            None => f.write_str(&self.target),
+        }
+    }
+}
+
+/// The wiki links in a Markdown body, in order. Links inside code are not
+/// links.
+pub fn parse(markdown: &str) -> Vec<WikiLink> {
+    let mut links = Vec::new();
+    let mut current: Option<WikiLink> = None;
+    for event in Parser::new_ext(markdown, Options::ENABLE_WIKILINKS) {
+        match event {
+            Event::Start(Tag::Link {
+                link_type: LinkType::WikiLink { has_pothole },
+                dest_url,
+                ..
+            }) => {
+                current = Some(WikiLink {
+                    target: dest_url.trim().to_string(),
+                    alias: has_pothole.then(String::new),
+                });
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(alias) = current.as_mut().and_then(|link| link.alias.as_mut()) {
+                    alias.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Link) => {
+                if let Some(mut link) = current.take() {
+                    if let Some(alias) = &mut link.alias {
+                        *alias = alias.trim().to_string();
+                    }
+                    if !link.note().is_empty() {
+                        links.push(link);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    links
+}
@@ -1,7 +1,11 @@
-use noters::{config::Config, error::{Result, NoterError}, note::NotesManager};
+use noters::{backup::{self, Archive}, config::Config, db::{Database, NoteRecord}, export::{CollisionPolicy, ExportFormat, ExportReport}, error::{Result, NoterError}, note::{KeySource, NoteFilter, NoteLink, NoteMatches, NotesManager, SortKey}, output::Output, output::OutputFormat, render, search::Snippet};
+use serde_json::json;
+use chrono::NaiveDate;
 use clap::{Parser, Subcommand};
 use colored::*;
+use std::io::IsTerminal;
 use std::path::PathBuf;
+use zeroize::Zeroizing;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -11,6 +15,9 @@ struct Cli {
 
     #[arg(global = true, short, long)]
     verbose: bool,
+
+    #[arg(global = true, long, default_value = "table", help = "Output format: table, plain or json")]
+    format: OutputFormat,
 }
 
 #[derive(Subcommand)]
@@ -18,27 +25,173 @@ enum Commands {
     New {
         #[arg(help = "Title of the new note")]
         title: Option<String>,
+        #[arg(long, help = "Body of the note, or - to read it from stdin")]
+        body: Option<String>,
+        #[arg(long, help = "Open the new note in your editor")]
+        edit: bool,
+    },
+    Append {
+        #[arg(help = "ID, UUID or title of the note to append to")]
+        note: String,
+        #[arg(help = "Text to append (read from stdin if omitted)")]
+        text: Option<String>,
+    },
+    List {
+        #[arg(long, help = "Only list notes with this tag")]
+        tag: Option<String>,
+        #[arg(long, default_value = "created", help = "Sort by created, updated or title")]
+        sort: SortKey,
+        #[arg(long, help = "Reverse the sort order")]
+        reverse: bool,
+        #[arg(long, value_parser = parse_day, help = "Only list notes created on or after this date")]
+        since: Option<NaiveDate>,
+        #[arg(long, value_parser = parse_day, help = "Only list notes created on or before this date")]
+        until: Option<NaiveDate>,
+        #[arg(long, help = "List at most this many notes")]
+        limit: Option<usize>,
     },
-    List,
     Delete {
-        #[arg(help = "ID of the note to delete")]
-        id: i64,
+        #[arg(help = "ID, UUID or title of the note to delete")]
+        note: String,
     },
     Edit {
-        #[arg(help = "ID of the note to edit")]
-        id: i64,
+        #[arg(help = "ID, UUID or title of the note to edit")]
+        note: String,
+    },
+    Show {
+        #[arg(help = "ID, UUID or title of the note to show")]
+        note: String,
+        #[arg(long, conflicts_with = "render", help = "Print the note as stored, without rendering")]
+        raw: bool,
+        #[arg(long, help = "Leave out the front matter")]
+        body: bool,
+        #[arg(long, help = "Render Markdown even when not writing to a terminal")]
+        render: bool,
+        #[arg(long, help = "Never page long notes")]
+        no_pager: bool,
     },
     Export {
         #[arg(help = "Directory to export notes to")]
         dir: Option<PathBuf>,
+        #[arg(long = "as", value_name = "FORMAT", default_value = "md", help = "File format: md, json or html")]
+        file_format: ExportFormat,
+        #[arg(long, conflicts_with_all = ["dir", "file_format", "skip", "rename"], help = "Write a .zip, .tar or .tar.gz archive instead (replacing an existing one)")]
+        archive: Option<PathBuf>,
+        #[arg(long = "id", value_delimiter = ',', help = "Only export these notes (IDs, UUIDs or titles)")]
+        ids: Vec<String>,
+        #[arg(long, help = "Only export notes with this tag")]
+        tag: Option<String>,
+        #[arg(long, help = "Only export notes matching this search query")]
+        query: Option<String>,
+        #[arg(long, value_parser = parse_day, help = "Only export notes created on or after this date")]
+        since: Option<NaiveDate>,
+        #[arg(long, value_parser = parse_day, help = "Only export notes created on or before this date")]
+        until: Option<NaiveDate>,
+        #[arg(long, group = "collision", help = "Replace existing files (default)")]
+        overwrite: bool,
+        #[arg(long, group = "collision", help = "Keep existing files and skip those notes")]
+        skip: bool,
+        #[arg(long, group = "collision", help = "Write beside existing files with a suffix")]
+        rename: bool,
+    },
+    Import {
+        #[arg(help = "Directory of .md/.txt files to import")]
+        dir: PathBuf,
     },
     Search {
-        #[arg(help = "Search query")]
+        #[arg(help = "Search query: words, \"phrases\", prefix*, AND/OR/NOT, -word, title:word, tag:name")]
         query: String,
+        #[arg(long, help = "Only match titles and filenames")]
+        titles: bool,
+    },
+    Rekey {
+        #[arg(long, conflicts_with = "key", help = "Protect the vault with a new master passphrase")]
+        passphrase: bool,
+        #[arg(long, help = "Switch to a newly generated key stored in config.toml")]
+        key: bool,
+    },
+    Privatize,
+    Links {
+        #[arg(required_unless_present = "broken", help = "ID, UUID or title of the note")]
+        note: Option<String>,
+        #[arg(long, help = "Only list broken links (in every note if no note is given)")]
+        broken: bool,
+    },
+    Backlinks {
+        #[arg(help = "ID, UUID or title of the note")]
+        note: String,
+    },
+    History {
+        #[arg(help = "ID, UUID or title of the note")]
+        note: String,
+    },
+    Diff {
+        #[arg(help = "ID, UUID or title of the note")]
+        note: String,
+        #[arg(help = "Revision to diff from")]
+        from: i64,
+        #[arg(help = "Revision to diff to (defaults to the current content)")]
+        to: Option<i64>,
+    },
+    Backup {
+        #[arg(help = "Archive file to write")]
+        file: PathBuf,
+    },
+    Restore {
+        #[arg(help = "ID, UUID or title of the note")]
+        note: String,
+        #[arg(help = "Revision to restore the note to")]
+        rev: i64,
+    },
+    RestoreBackup {
+        #[arg(help = "Backup archive to restore the vault from")]
+        file: PathBuf,
+        #[arg(long, help = "Restore the backup into this notes directory")]
+        notes_dir: Option<PathBuf>,
+        #[arg(long, help = "Restore the backup database to this path")]
+        db_path: Option<PathBuf>,
+        #[arg(long, help = "Replace an existing vault")]
+        force: bool,
+        #[arg(long, help = "Make the restored vault the configured one")]
+        switch: bool,
+    },
+    Db {
+        #[command(subcommand)]
+        action: DbCommand,
+    },
+    Tags,
+    Tui,
+    Tag {
+        #[command(subcommand)]
+        action: TagCommand,
     },
 }
 
-fn main() -> Result<()> {
+#[derive(Subcommand)]
+enum DbCommand {
+    Migrate {
+        #[arg(long, help = "Show pending migrations without applying them")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommand {
+    Rename {
+        #[arg(help = "Current tag name")]
+        from: String,
+        #[arg(help = "New tag name")]
+        to: String,
+    },
+    Merge {
+        #[arg(required = true, help = "Tags to merge")]
+        tags: Vec<String>,
+        #[arg(long, help = "Tag to merge them into")]
+        into: String,
+    },
+}
+
+fn main() {
     let cli = Cli::parse();
     
     if cli.verbose {
@@ -49,16 +202,46 @@ fn main() -> Result<()> {
         env_logger::init();
     }
 
+    let out = Output::new(cli.format);
+    if let Err(e) = run(cli.command, out) {
+        out.error(&e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run(command: Option<Commands>, out: Output) -> Result<()> {
     let config = Config::load()?;
-    let notes_manager = NotesManager::new(config)?;
 
-    match cli.command {
-        Some(Commands::New { title }) => {
+    // Database maintenance runs before `NotesManager` would migrate the
+    // schema implicitly, and a vault restore replaces the vault entirely.
+    let command = match command {
+        Some(Commands::Db { action }) => return run_db_command(&config, action, out),
+        Some(Commands::RestoreBackup { file, notes_dir, db_path, force, switch }) => {
+            return run_restore(config, file, notes_dir, db_path, force, switch, out);
+        }
+        command => command,
+    };
+
+    let mut notes_manager = NotesManager::new(config)?;
+    for path in notes_manager.unplaced_edits() {
+        out.warning(&format!(
+            "Found unsaved edits of a note that no longer exists at {}. Delete the file once you have what you need.",
+            path.display()
+        ));
+    }
+    for (id, rev) in notes_manager.recovered_edits() {
+        out.warning(&format!(
+            "Recovered unsaved edits of note {} as revision {}. Run `noters restore {} {}` to apply them.",
+            id, rev, id, rev
+        ));
+    }
+
+    match command {
+        Some(Commands::New { title, body, edit }) => {
             let title = title.unwrap_or_else(|| noters::utils::get_input("Note title: ").trim().to_string());
             let title = title.trim();
             if title.is_empty() {
-                println!("{}", "Error: Title cannot be empty".red());
-                return Ok(());
+                return Err(NoterError::InvalidTitle("Title cannot be empty".to_string()));
             }
             
             let title_without_ext = match std::path::Path::new(&title).extension() {
@@ -66,95 +249,586 @@ fn main() -> Result<()> {
                 None => title,
             };
             
-            notes_manager.create_note(title_without_ext)?;
-            println!("{}", "Note created successfully.".green());
+            let body = match body {
+                Some(body) if body == "-" => noters::utils::read_stdin()?,
+                Some(body) => Zeroizing::new(body),
+                None => Zeroizing::new(String::new()),
+            };
+            let id = notes_manager.create_note(title_without_ext, &body)?;
+            if edit {
+                notes_manager.edit_note(id)?;
+            }
+            if out.is_json() {
+                out.json(&notes_manager.get_note(id)?)?;
+            }
+            out.success("Note created successfully.");
+        }
+        Some(Commands::List { tag, sort, reverse, since, until, limit }) => {
+            let filter = NoteFilter { tag, since, until, ..NoteFilter::default() };
+            let mut notes = notes_manager.select_notes(&filter)?;
+            sort.sort(&mut notes, reverse);
+            if let Some(limit) = limit {
+                notes.truncate(limit);
+            }
+            out.notes(&notes, "No notes found.")?;
         }
-        Some(Commands::List) => {
-            let notes = notes_manager.list_notes()?;
-            if notes.is_empty() {
-                println!("{}", "No notes found.".yellow());
+        Some(Commands::Append { note, text }) => {
+            let id = resolve_note(&notes_manager, &note, true, out)?;
+            let text = match text {
+                Some(text) => Zeroizing::new(text),
+                None => noters::utils::read_stdin()?,
+            };
+            if text.trim().is_empty() {
+                return Err(NoterError::InvalidInput("Nothing to append".to_string()));
+            }
+            notes_manager.append_note(id, &text)?;
+            if out.is_json() {
+                out.json(&notes_manager.get_note(id)?)?;
+            }
+            out.success("Note updated successfully.");
+        }
+        Some(Commands::Delete { note }) => {
+            let id = resolve_note(&notes_manager, &note, true, out)?;
+            let backlinks = notes_manager.backlinks(id)?;
+            if !notes_manager.delete_note(id)? {
+                return Err(NoterError::NoteNotFound(id));
+            }
+            if out.is_json() {
+                out.json(&json!({ "deleted": id }))?;
+            }
+            out.success("Note deleted successfully.");
+            warn_broken_links(&backlinks, id, out);
+        }
+        Some(Commands::Edit { note }) => {
+            let id = resolve_note(&notes_manager, &note, false, out)?;
+            let backlinks = notes_manager.backlinks(id)?;
+            notes_manager.edit_note(id)?;
+            if out.is_json() {
+                out.json(&notes_manager.get_note(id)?)?;
+            }
+            out.success("Note edited successfully.");
+            // A new title breaks links that named the old one.
+            let still_linked = notes_manager.backlinks(id)?;
+            let broken: Vec<_> = backlinks
+                .into_iter()
+                .filter(|link| !still_linked.iter().any(|l| l.source.id == link.source.id && l.target == link.target))
+                .collect();
+            warn_broken_links(&broken, id, out);
+        }
+        Some(Commands::Show { note, raw, body, render, no_pager }) => {
+            let id = resolve_note(&notes_manager, &note, false, out)?;
+            let note = notes_manager.get_note(id)?;
+            let content = notes_manager.read_note(id)?;
+            let text = if body { noters::frontmatter::body(&content) } else { content.as_str() };
+            if out.is_json() {
+                return out.json(&json!({ "note": note, "content": text }));
+            }
+
+            // Markdown is rendered for people; pipes get the note as stored
+            // unless `--render` asks otherwise.
+            let terminal = std::io::stdout().is_terminal();
+            let rendered = !raw && (render || (terminal && out.format() == OutputFormat::Table));
+            let text = Zeroizing::new(match (rendered, body) {
+                (true, true) => render::markdown(text),
+                (true, false) => render::note(&note, &content),
+                (false, _) => text.to_string(),
+            });
+
+            let long = noters::utils::terminal_height().is_some_and(|rows| text.lines().count() >= rows);
+            if terminal && long && !no_pager {
+                noters::utils::page(&text)?;
             } else {
-                for note in notes {
-                    println!("{} {} {}", 
-                        format!("[{}]", note.id).cyan(),
-                        note.title.bright_white(),
-                        format!("({})", note.filename).dimmed()
-                    );
+                out.raw(&text)?;
+            }
+        }
+        Some(Commands::Export { dir, file_format, archive, ids, tag, query, since, until, overwrite: _, skip, rename }) => {
+            let ids = ids
+                .iter()
+                .map(|note| resolve_note(&notes_manager, note, false, out))
+                .collect::<Result<Vec<i64>>>()?;
+            let filter = NoteFilter { ids, tag, query, since, until };
+            let notes = notes_manager.select_notes(&filter)?;
+
+            let report = match archive {
+                Some(ref archive) => notes_manager.export_archive(&notes, archive)?,
+                None if notes.is_empty() => ExportReport::default(),
+                None => {
+                    if let Some(ref dir) = dir {
+                        if !dir.exists() {
+                            std::fs::create_dir_all(dir)?;
+                        } else if !dir.is_dir() {
+                            return Err(NoterError::InvalidInput(format!("{} is not a directory", dir.display())));
+                        }
+                    }
+
+                    let policy = if skip {
+                        CollisionPolicy::Skip
+                    } else if rename {
+                        CollisionPolicy::Rename
+                    } else {
+                        CollisionPolicy::Overwrite
+                    };
+                    notes_manager.export_notes(&notes, dir.as_deref(), file_format, policy)?
+                }
+            };
+
+            if out.is_json() {
+                out.json(&json!({
+                    "exported": report.exported,
+                    "skipped": report.skipped,
+                    "failed": report.failed,
+                    "total": report.total(),
+                }))?;
+            } else if report.total() == 0 {
+                out.notice("No notes to export.");
+            } else if report.exported == report.total() {
+                out.success(&format!("Successfully exported all {} notes.", report.total()));
+            } else {
+                out.notice(&format!("Exported {}/{} notes successfully.", report.exported, report.total()));
+                if report.skipped > 0 {
+                    out.notice(&format!("Skipped {} notes whose export file already exists.", report.skipped));
+                }
+                if report.failed > 0 {
+                    out.notice("Check the application logs for details about failed exports.");
                 }
             }
         }
-        Some(Commands::Delete { id }) => {
-            match notes_manager.delete_note(id)? {
-                true => println!("{}", "Note deleted successfully.".green()),
-                false => println!("{}", "Note not found.".red()),
+        Some(Commands::Import { dir }) => {
+            let report = notes_manager.import_dir(&dir)?;
+            if out.is_json() {
+                let files = |entries: &[(PathBuf, i64)]| -> Vec<serde_json::Value> {
+                    entries.iter().map(|(path, id)| json!({ "path": path, "id": id })).collect()
+                };
+                let failed: Vec<serde_json::Value> = report.failed.iter().map(|(path, e)| json!({
+                    "path": path,
+                    "code": e.code(),
+                    "message": e.to_string(),
+                })).collect();
+                return out.json(&json!({
+                    "imported": files(&report.imported),
+                    "duplicates": files(&report.duplicates),
+                    "failed": failed,
+                }));
+            }
+
+            for (path, id) in &report.duplicates {
+                out.notice(&format!("Skipped duplicate {} (same as note {})", path.display(), id));
+            }
+            for (path, e) in &report.failed {
+                out.warning(&format!("Failed to import {}: {}", path.display(), e));
+            }
+            if report.imported.is_empty() && report.duplicates.is_empty() && report.failed.is_empty() {
+                out.notice("No .md or .txt files found.");
+            } else {
+                out.success(&format!(
+                    "Imported {} notes ({} duplicates, {} failed).",
+                    report.imported.len(), report.duplicates.len(), report.failed.len()
+                ));
             }
         }
-        Some(Commands::Edit { id }) => {
-            match notes_manager.edit_note(id) {
-                Ok(_) => println!("{}", "Note edited successfully.".green()),
-                Err(NoterError::EditorNotFound) => {
-                    println!("{}", "No editor configured. Set $EDITOR environment variable or specify 'editor' in config.toml".red());
+        Some(Commands::Search { query, titles: true }) => {
+            let results = notes_manager.search_notes(&query)?;
+            out.notes(&results, "No matching notes found.")?;
+        }
+        Some(Commands::Search { query, titles: false }) => {
+            let hits = notes_manager.search_content(&query)?;
+            if out.is_json() {
+                return out.json(&hits);
+            }
+            if hits.is_empty() {
+                out.notice("No matching notes found.");
+            } else {
+                for hit in hits {
+                    println!("{} {} {}",
+                        format!("[{}]", hit.note.id).cyan(),
+                        hit.note.title.bright_white(),
+                        format!("({:.2})", hit.score).dimmed()
+                    );
+                    if let Some(snippet) = hit.snippet {
+                        println!("    {}", highlight_snippet(&snippet).as_str());
+                    }
                 }
-                Err(NoterError::NoteNotFound(_)) => println!("{}", "Note not found.".red()),
-                Err(e) => println!("{} {}", "Error editing note:".red(), e),
-            }
-        }
-        Some(Commands::Export { dir }) => {
-            if let Some(ref dir) = dir {
-                if !dir.exists() {
-                    std::fs::create_dir_all(dir).map_err(|e| {
-                        println!("{} {}", "Failed to create export directory:".red(), e);
-                        e
-                    })?;
-                } else if !dir.is_dir() {
-                    println!("{} {}", "Error:".red(), format!("{} is not a directory", dir.display()));
-                    return Ok(());
+            }
+        }
+        Some(Commands::Rekey { passphrase, key }) => {
+            let target = if passphrase {
+                KeySource::Passphrase
+            } else if key {
+                KeySource::Config
+            } else {
+                notes_manager.key_source()?
+            };
+
+            let count = notes_manager.rekey(target)?;
+            if out.is_json() {
+                out.json(&json!({ "reencrypted": count }))?;
+            }
+            out.success(&format!("Re-encrypted {} notes with the new key.", count));
+        }
+        Some(Commands::Privatize) => {
+            if notes_manager.private_metadata() {
+                if out.is_json() {
+                    out.json(&json!({ "migrated": 0 }))?;
                 }
+                out.notice("Private metadata is already enabled.");
+                return Ok(());
             }
 
-            match notes_manager.export_notes(dir.as_deref()) {
-                Ok((success_count, total_count)) => {
-                    if success_count == 0 && total_count == 0 {
-                        println!("{}", "No notes to export.".yellow());
-                    } else if success_count == total_count {
-                        println!("{}", format!("Successfully exported all {} notes.", total_count).green());
-                    } else {
-                        println!("{}", format!("Exported {}/{} notes successfully.", success_count, total_count).yellow());
-                        println!("{}", "Check the application logs for details about failed exports.".yellow());
-                    }
+            let count = notes_manager.enable_private_metadata()?;
+            if out.is_json() {
+                out.json(&json!({ "migrated": count }))?;
+            }
+            out.success(&format!("Encrypted metadata of {} notes.", count));
+        }
+        Some(Commands::Tags) => {
+            let tags = notes_manager.tag_counts()?;
+            if out.is_json() {
+                let tags: Vec<serde_json::Value> = tags
+                    .iter()
+                    .map(|(name, count)| json!({ "name": name, "count": count }))
+                    .collect();
+                return out.json(&tags);
+            }
+            if tags.is_empty() {
+                out.notice("No tags found.");
+            } else {
+                for (name, count) in tags {
+                    println!("{} {}", name.bright_white(), format!("({})", count).dimmed());
                 }
-                Err(e) => println!("{} {}", "Error during export:".red(), e),
             }
         }
-        Some(Commands::Search { query }) => {
-            let results = notes_manager.search_notes(&query)?;
-            if results.is_empty() {
-                println!("{}", "No matching notes found.".yellow());
+        Some(Commands::Tui) => {
+            if out.is_json() || !std::io::stdout().is_terminal() || !std::io::stdin().is_terminal() {
+                return Err(NoterError::InvalidInput("The TUI needs an interactive terminal".to_string()));
+            }
+            noters::tui::run(&notes_manager)?;
+        }
+        Some(Commands::Tag { action }) => {
+            let count = match action {
+                TagCommand::Rename { from, to } => notes_manager.rename_tag(&from, &to)?,
+                TagCommand::Merge { tags, into } => notes_manager.merge_tags(&tags, &into)?,
+            };
+            if out.is_json() {
+                out.json(&json!({ "updated": count }))?;
+            }
+            out.success(&format!("Updated tags in {} notes.", count));
+        }
+        Some(Commands::Links { note: Some(note), broken }) => {
+            let id = resolve_note(&notes_manager, &note, false, out)?;
+            let mut links = notes_manager.links(id)?;
+            if broken {
+                links.retain(|link| link.note.is_none());
+            }
+            out.links(&links, false, if broken { "No broken links in this note." } else { "This note has no links." })?;
+        }
+        Some(Commands::Links { note: None, .. }) => {
+            let links = notes_manager.broken_links()?;
+            out.links(&links, true, "No broken links.")?;
+        }
+        Some(Commands::Backlinks { note }) => {
+            let id = resolve_note(&notes_manager, &note, false, out)?;
+            let mut sources: Vec<NoteRecord> = Vec::new();
+            for link in notes_manager.backlinks(id)? {
+                if !sources.iter().any(|note| note.id == link.source.id) {
+                    sources.push(link.source);
+                }
+            }
+            out.notes(&sources, "No notes link to this note.")?;
+        }
+        Some(Commands::History { note }) => {
+            let id = resolve_note(&notes_manager, &note, false, out)?;
+            let revisions = notes_manager.history(id)?;
+            if out.is_json() {
+                let revisions: Vec<serde_json::Value> = revisions
+                    .iter()
+                    .map(|revision| json!({
+                        "rev": revision.rev,
+                        "created_at": revision.created_at,
+                        "lines": revision.content.lines().count(),
+                    }))
+                    .collect();
+                return out.json(&revisions);
+            }
+            if revisions.is_empty() {
+                out.notice("No revisions recorded for this note.");
             } else {
-                for note in results {
-                    println!("{} {} {}", 
-                        format!("[{}]", note.id).cyan(),
-                        note.title.bright_white(),
-                        format!("({})", note.filename).dimmed()
+                for revision in revisions {
+                    println!("{} {} {}",
+                        format!("[{}]", revision.rev).cyan(),
+                        revision.created_at.format("%Y-%m-%d %H:%M:%S").to_string().bright_white(),
+                        format!("({} lines)", revision.content.lines().count()).dimmed()
                     );
                 }
             }
         }
+        Some(Commands::Diff { note, from, to }) => {
+            let id = resolve_note(&notes_manager, &note, false, out)?;
+            let diff = notes_manager.diff_revisions(id, from, to)?;
+            if out.is_json() {
+                return out.json(&json!({ "id": id, "from": from, "to": to, "diff": diff }));
+            }
+            if diff.is_empty() {
+                out.notice("No differences.");
+            }
+            for line in diff.lines() {
+                if line.starts_with("+++") || line.starts_with("---") {
+                    println!("{}", line.bold());
+                } else if line.starts_with('+') {
+                    println!("{}", line.green());
+                } else if line.starts_with('-') {
+                    println!("{}", line.red());
+                } else if line.starts_with("@@") {
+                    println!("{}", line.cyan());
+                } else {
+                    println!("{}", line);
+                }
+            }
+        }
+        Some(Commands::Backup { file }) => {
+            let passphrase = noters::utils::get_backup_passphrase(true)?;
+            let count = notes_manager.backup(&file, &passphrase)?;
+            if out.is_json() {
+                out.json(&json!({ "file": file, "notes": count }))?;
+            }
+            out.success(&format!("Backed up {} notes to {}.", count, file.display()));
+        }
+        Some(Commands::Restore { note, rev }) => {
+            let id = resolve_note(&notes_manager, &note, true, out)?;
+            notes_manager.restore_revision(id, rev)?;
+            if out.is_json() {
+                out.json(&json!({ "id": id, "rev": rev }))?;
+            }
+            out.success(&format!("Restored note {} to revision {}.", id, rev));
+        }
+        Some(Commands::Db { .. }) | Some(Commands::RestoreBackup { .. }) => {
+            unreachable!("handled before opening the vault")
+        }
         None => print_usage(),
     }
 
     Ok(())
 }
 
+/// Resolves a note given as an ID, UUID or title query. When several notes
+/// match, the user picks one from a ranked list; without a terminal (or with
+/// JSON output) that is an error naming the best matches. With `destructive`,
+/// a single partial or fuzzy match must be confirmed first, and without a
+/// terminal only an ID, UUID or exact title is accepted.
+fn resolve_note(notes_manager: &NotesManager, query: &str, destructive: bool, out: Output) -> Result<i64> {
+    const SHOWN: usize = 10;
+
+    let NoteMatches { notes: matches, exact } = notes_manager.find_notes(query)?;
+    let interactive = !out.is_json() && std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+    match matches.as_slice() {
+        [] => return Err(NoterError::NoMatchingNote(query.to_string())),
+        [note] if exact || !destructive => return Ok(note.id),
+        [note] => {
+            let label = format!("[{}] {}", note.id, note.title.as_str());
+            if !interactive {
+                return Err(NoterError::InexactNote(query.to_string(), label));
+            }
+            let prompt = format!("'{}' best matches {}. Continue?", query, label);
+            if !noters::utils::confirm(&prompt)? {
+                return Err(NoterError::InvalidInput("No note selected".to_string()));
+            }
+            return Ok(note.id);
+        }
+        _ => {}
+    }
+
+    if !interactive {
+        let mut candidates: Vec<String> = matches
+            .iter()
+            .take(5)
+            .map(|note| format!("[{}] {}", note.id, note.title.as_str()))
+            .collect();
+        if matches.len() > 5 {
+            candidates.push(format!("and {} more", matches.len() - 5));
+        }
+        return Err(NoterError::AmbiguousNote(query.to_string(), candidates.join(", ")));
+    }
+
+    let options: Vec<String> = matches
+        .iter()
+        .take(SHOWN)
+        .map(|note| format!("{} {} {}",
+            note.title.bright_white(),
+            format!("[{}]", note.id).cyan(),
+            note.updated_at.format("%Y-%m-%d").to_string().dimmed()
+        ))
+        .collect();
+    let heading = if matches.len() > SHOWN {
+        format!("{} notes match '{}', best {} shown:", matches.len(), query, SHOWN)
+    } else {
+        format!("{} notes match '{}':", matches.len(), query)
+    };
+    match noters::utils::pick(&heading, &options)? {
+        Some(index) => Ok(matches[index].id),
+        None => Err(NoterError::InvalidInput("No note selected".to_string())),
+    }
+}
+
+fn run_db_command(config: &Config, action: DbCommand, out: Output) -> Result<()> {
+    let db = Database::open(config.db_path.clone())?;
+    match action {
+        DbCommand::Migrate { dry_run: true } => {
+            let pending = db.pending_migrations()?;
+            if out.is_json() {
+                let pending: Vec<serde_json::Value> = pending
+                    .iter()
+                    .map(|m| json!({ "version": m.version, "description": m.description }))
+                    .collect();
+                return out.json(&json!({ "schema_version": db.schema_version()?, "pending": pending }));
+            }
+            println!("Schema version: {}", db.schema_version()?);
+            if pending.is_empty() {
+                out.success("Database is up to date.");
+            } else {
+                println!("{}", "Pending migrations:".yellow());
+                for migration in pending {
+                    println!("  {} {}", format!("[{}]", migration.version).cyan(), migration.description);
+                }
+            }
+        }
+        DbCommand::Migrate { dry_run: false } => {
+            let report = db.migrate()?;
+            if out.is_json() {
+                let applied: Vec<serde_json::Value> = report
+                    .applied
+                    .iter()
+                    .map(|m| json!({ "version": m.version, "description": m.description }))
+                    .collect();
+                return out.json(&json!({
+                    "from": report.from,
+                    "to": db.schema_version()?,
+                    "applied": applied,
+                    "backup": report.backup,
+                }));
+            }
+            if report.applied.is_empty() {
+                out.success("Database is up to date.");
+                return Ok(());
+            }
+            if let Some(backup) = report.backup {
+                println!("{} {}", "Backed up database to".dimmed(), backup.display());
+            }
+            for migration in &report.applied {
+                println!("  {} {}", format!("[{}]", migration.version).cyan(), migration.description);
+            }
+            out.success(&format!("Migrated database from version {} to {}.", report.from, db.schema_version()?));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a `--since`/`--until` date, accepting anything front matter does.
+fn parse_day(value: &str) -> std::result::Result<NaiveDate, String> {
+    noters::frontmatter::parse_date(value)
+        .map(|date| date.date_naive())
+        .ok_or_else(|| format!("invalid date '{}' (expected YYYY-MM-DD)", value))
+}
+
+fn run_restore(
+    mut config: Config,
+    file: PathBuf,
+    notes_dir: Option<PathBuf>,
+    db_path: Option<PathBuf>,
+    force: bool,
+    switch: bool,
+    out: Output,
+) -> Result<()> {
+    let passphrase = noters::utils::get_backup_passphrase(false)?;
+    let archive = Archive::open(&file, &passphrase)?;
+
+    let notes_dir = notes_dir.unwrap_or_else(|| config.notes_dir.clone());
+    let db_path = db_path.unwrap_or_else(|| config.db_path.clone());
+    let count = backup::restore(&mut config, &archive, notes_dir.clone(), db_path.clone(), force, switch)?;
+    let active = config.notes_dir == notes_dir && config.db_path == db_path;
+    if out.is_json() {
+        return out.json(&json!({
+            "notes": count,
+            "created_at": archive.manifest.created_at,
+            "notes_dir": notes_dir,
+            "db_path": db_path,
+            "configured": active,
+        }));
+    }
+    out.success(&format!(
+        "Restored {} notes from backup taken {}.",
+        count, archive.manifest.created_at
+    ));
+    println!("{} {}", "Notes directory:".dimmed(), notes_dir.display());
+    println!("{} {}", "Database:".dimmed(), db_path.display());
+    if !active {
+        out.notice("config.toml still points at the previous vault; run restore-backup with --switch to use this one.");
+    }
+    Ok(())
+}
+
+fn highlight_snippet(snippet: &Snippet) -> Zeroizing<String> {
+    let mut out = Zeroizing::new(String::with_capacity(snippet.text.len() * 2));
+    let mut pos = 0;
+    for &(start, end) in &snippet.highlights {
+        out.push_str(&snippet.text[pos..start].dimmed().to_string());
+        out.push_str(&snippet.text[start..end].yellow().bold().to_string());
+        pos = end;
+    }
+    out.push_str(&snippet.text[pos..].dimmed().to_string());
+    out
+}
+
+/// Warns about links to note `id` that no longer resolve, e.g. after it
+/// was renamed or deleted.
+fn warn_broken_links(links: &[NoteLink], id: i64, out: Output) {
+    let mut sources: Vec<String> = Vec::new();
+    for link in links {
+        let source = format!("[{}] {}", link.source.id, link.source.title.as_str());
+        if link.source.id != id && !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+    if !sources.is_empty() {
+        out.warning(&format!(
+            "Links to this note are now broken in: {}. See 'noters links --broken'.",
+            sources.join(", ")
+        ));
+    }
+}
+
 fn print_usage() {
     const USAGE: &str = "Usage: noters <command> [args]";
     const COMMANDS: &[(&str, &str)] = &[
-        ("new [title]", "Create a new note"),
-        ("list", "List all notes"),
-        ("delete <id>", "Delete a note by ID"),
-        ("edit <id>", "Edit a note in your configured editor"),
-        ("export [dir]", "Export all notes to directory (defaults to configured export dir)"),
-        ("search <query>", "Search notes"),
+        ("<command> --format json|table|plain", "Choose the output format of any command"),
+        ("new [title] [--body <text>|-] [--edit]", "Create a new note; --body - reads the body from stdin"),
+        ("append <note> [text]", "Append text or stdin to a note"),
+        ("list [--tag <tag>]", "List all notes, optionally by tag"),
+        ("list --sort created|updated|title [--reverse]", "Choose the order of the list"),
+        ("list --since/--until <date> [--limit <n>]", "List notes created in a date range"),
+        ("delete <note>", "Delete a note by ID, UUID or title"),
+        ("edit <note>", "Edit a note in your configured editor"),
+        ("show <note> [--raw|--body]", "Print a note, rendered and paged on a terminal"),
+        ("export [dir] [--as md|json|html]", "Export all notes to directory (defaults to configured export dir)"),
+        ("export --archive <file>", "Export all notes to a .zip or .tar.gz archive"),
+        ("export --tag/--query/--id/--since/--until", "Export a subset of notes"),
+        ("export --overwrite/--skip/--rename", "Choose how existing export files are handled"),
+        ("import <dir>", "Import .md/.txt files with their front matter"),
+        ("search <query>", "Search note titles and contents"),
+        ("search title:<word> tag:<name>", "Limit a search to titles or a tag"),
+        ("rekey", "Re-encrypt all notes with a new key"),
+        ("privatize", "Encrypt note titles and use opaque filenames"),
+        ("links <note> [--broken]", "List the [[wiki links]] in a note"),
+        ("links --broken", "List links to missing notes in every note"),
+        ("backlinks <note>", "List notes that link to a note"),
+        ("history <note>", "List saved revisions of a note"),
+        ("diff <note> <rev> [rev]", "Show changes between revisions"),
+        ("restore <note> <rev>", "Restore a note to a revision"),
+        ("backup <file>", "Write an encrypted backup of the vault"),
+        ("restore-backup <file> [--force] [--switch]", "Restore the vault from a backup"),
+        ("db migrate [--dry-run]", "Apply pending database migrations"),
+        ("tui", "Browse, filter and edit notes full-screen"),
+        ("tags", "List tags with note counts"),
+        ("tag rename <from> <to>", "Rename a tag in every note"),
+        ("tag merge <tags>.. --into <tag>", "Merge tags into one"),
     ];
 
     println!("\n{}", "╭─────────────────────────────────────╮".bright_blue());
@@ -163,8 +837,9 @@ fn print_usage() {
     
     println!("\n{}", "Commands:".bright_yellow().bold());
     
+    let width = COMMANDS.iter().map(|(cmd, _)| cmd.len()).max().unwrap_or(15);
     for (cmd, description) in COMMANDS {
-        println!("  {} {:<15} │ {}", "►".bright_green(), cmd.bright_cyan(), description);
+        println!("  {} {:<width$} │ {}", "►".bright_green(), cmd.bright_cyan(), description);
     }
-    println!("");
+    println!();
 }
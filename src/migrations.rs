@@ -0,0 +1,171 @@
+//! Ordered schema migrations for the notes database.
+//!
+//! The schema version is tracked in `PRAGMA user_version`. Each migration
+//! runs in its own transaction together with the version bump, so a failed
+//! migration leaves the database at the previous version. Databases created
+//! before versioning existed report version 0; the early migrations are
+//! written to be no-ops on the tables those versions already created.
+
+use crate::error::Result;
+use rusqlite::{params, Connection};
+use uuid::Uuid;
+
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    up: fn(&Connection) -> Result<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create notes table",
+        up: create_notes,
+    },
+    Migration {
+        version: 2,
+        description: "Create vault_meta table",
+        up: create_vault_meta,
+    },
+    Migration {
+        version: 3,
+        description: "Add note UUIDs",
+        up: add_note_uuids,
+    },
+    Migration {
+        version: 4,
+        description: "Create tags and note_tags tables",
+        up: create_tags,
+    },
+    Migration {
+        version: 5,
+        description: "Create revisions table",
+        up: create_revisions,
+    },
+    Migration {
+        version: 6,
+        description: "Create links table",
+        up: create_links,
+    },
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+pub fn current_version(conn: &Connection) -> Result<i64> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+pub fn pending(conn: &Connection) -> Result<Vec<&'static Migration>> {
+    let current = current_version(conn)?;
+    Ok(MIGRATIONS.iter().filter(|m| m.version > current).collect())
+}
+
+/// Applies `migration` and records its version in one transaction.
+pub fn apply(conn: &Connection, migration: &Migration) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    (migration.up)(&tx)?;
+    tx.pragma_update(None, "user_version", migration.version)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn create_notes(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY,
+            title TEXT NOT NULL,
+            filename TEXT NOT NULL UNIQUE,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_vault_meta(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS vault_meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Adds the `uuid` column and gives every existing note a fresh UUID.
+fn add_note_uuids(conn: &Connection) -> Result<()> {
+    let has_uuid = conn
+        .prepare("SELECT 1 FROM pragma_table_info('notes') WHERE name = 'uuid'")?
+        .exists([])?;
+    if has_uuid {
+        return Ok(());
+    }
+
+    conn.execute("ALTER TABLE notes ADD COLUMN uuid TEXT", [])?;
+    let ids = conn
+        .prepare("SELECT id FROM notes")?
+        .query_map([], |row| row.get(0))?
+        .collect::<std::result::Result<Vec<i64>, _>>()?;
+    for id in ids {
+        conn.execute(
+            "UPDATE notes SET uuid = ?1 WHERE id = ?2",
+            params![Uuid::new_v4().to_string(), id],
+        )?;
+    }
+    conn.execute("CREATE UNIQUE INDEX idx_notes_uuid ON notes(uuid)", [])?;
+    Ok(())
+}
+
+fn create_tags(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_tags (
+            note_id INTEGER NOT NULL REFERENCES notes(id),
+            tag_id INTEGER NOT NULL REFERENCES tags(id),
+            PRIMARY KEY (note_id, tag_id)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_revisions(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE revisions (
+            id INTEGER PRIMARY KEY,
+            note_id INTEGER NOT NULL REFERENCES notes(id),
+            rev INTEGER NOT NULL,
+            created_at TEXT NOT NULL,
+            content TEXT NOT NULL,
+            UNIQUE (note_id, rev)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// One row per `[[wiki link]]`. `link` holds the text between the brackets,
+/// encrypted like titles when metadata is private; targets are resolved when
+/// read so that renames and deletions show up as broken links.
+fn create_links(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE links (
+            id INTEGER PRIMARY KEY,
+            note_id INTEGER NOT NULL REFERENCES notes(id),
+            link TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX idx_links_note ON links(note_id)", [])?;
+    Ok(())
+}
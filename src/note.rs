@@ -1,116 +1,1033 @@
+use crate::backup::{self, ArchiveWriter};
 use crate::config::Config;
-use crate::crypto::Crypto;
-use crate::db::{Database, NoteRecord};
+use crate::crypto::{self, Binding, Crypto, KdfParams};
+use crate::db::{Database, NoteRecord, RekeyedColumns, RevisionRecord};
 use crate::error::{NoterError, Result};
-use chrono::Local;
+use crate::export::{
+    self, CollisionPolicy, ExportArchive, ExportFormat, ExportReport, ExportedNote, ManifestEntry,
+};
+use crate::frontmatter::{self, NoteMetadata};
+use crate::fuzzy;
+use crate::links::{self, WikiLink};
+use crate::rekey::{self, RekeyJournal};
+use crate::search::{Query, SearchHit, SearchIndex};
+use crate::staging::{self, PlaintextFile};
+use chrono::{DateTime, Local, NaiveDate};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use log::{info, warn};
+use serde::Serialize;
 use std::fs;
 use std::path::PathBuf;
 use std::path::Path;
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+/// Where the vault key comes from after `rekey`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    /// A freshly generated `encryption_key` stored in config.toml.
+    Config,
+    /// A master passphrase run through Argon2id.
+    Passphrase,
+}
+
+/// Outcome of [`NotesManager::import_dir`].
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Imported files and the IDs of the notes created for them.
+    pub imported: Vec<(PathBuf, i64)>,
+    /// Files whose content matches a note already in the vault, with the
+    /// ID of that note.
+    pub duplicates: Vec<(PathBuf, i64)>,
+    pub failed: Vec<(PathBuf, NoterError)>,
+}
+
+/// Criteria for selecting a subset of notes. Empty criteria match every
+/// note; dates compare against the day a note was created, inclusively.
+#[derive(Debug, Clone, Default)]
+pub struct NoteFilter {
+    pub ids: Vec<i64>,
+    pub tag: Option<String>,
+    /// A full-text search query, see [`crate::search::Query`].
+    pub query: Option<String>,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+/// A saved version of a note, decrypted.
+#[derive(Debug)]
+pub struct Revision {
+    pub rev: i64,
+    pub created_at: DateTime<Local>,
+    pub content: Zeroizing<String>,
+}
+
+/// Notes found by `find_notes`, best first. `exact` is false when they
+/// only matched part of the query or matched it fuzzily.
+#[derive(Debug)]
+pub struct NoteMatches {
+    pub notes: Vec<NoteRecord>,
+    pub exact: bool,
+}
+
+/// A `[[wiki link]]` from one note to another, resolved against the
+/// current titles and UUIDs.
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteLink {
+    /// The note containing the link.
+    pub source: NoteRecord,
+    /// The title or UUID as written, with any `#section`.
+    pub target: String,
+    pub alias: Option<String>,
+    /// The note linked to, or `None` if the link is broken.
+    pub note: Option<NoteRecord>,
+}
+
+/// Orders of `noters list`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    /// Newest first.
+    #[default]
+    Created,
+    /// Most recently changed first.
+    Updated,
+    /// Alphabetically, ignoring case.
+    Title,
+}
+
+impl SortKey {
+    /// Sorts `notes` by this key, breaking ties by ID, and reverses the
+    /// result if `reverse` is set.
+    pub fn sort(self, notes: &mut [NoteRecord], reverse: bool) {
+        match self {
+            Self::Created => notes.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id))),
+            Self::Updated => notes.sort_by(|a, b| b.updated_at.cmp(&a.updated_at).then(b.id.cmp(&a.id))),
+            Self::Title => notes.sort_by(|a, b| {
+                a.title
+                    .to_lowercase()
+                    .cmp(&b.title.to_lowercase())
+                    .then(a.id.cmp(&b.id))
+            }),
+        }
+        if reverse {
+            notes.reverse();
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "created" => Ok(Self::Created),
+            "updated" => Ok(Self::Updated),
+            "title" => Ok(Self::Title),
+            other => Err(format!("unknown sort order '{}' (expected created, updated or title)", other)),
+        }
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::Title => "title",
+        })
+    }
+}
+
+const TAG_BINDING: Binding<'static> = Binding {
+    id: "tag",
+    context: "",
+};
 
 pub struct NotesManager {
     config: Config,
     db: Database,
     crypto: Crypto,
     notes_dir: PathBuf,
+    /// Titles are stored encrypted and filenames are opaque.
+    private_metadata: bool,
+    /// `(note id, revision)` of unsaved edits recovered at startup.
+    recovered_edits: Vec<(i64, i64)>,
+    /// Staged edits left behind for notes this vault does not know.
+    unplaced_edits: Vec<PathBuf>,
 }
 
 impl NotesManager {
-    pub fn new(config: Config) -> Result<Self> {
+    pub fn new(mut config: Config) -> Result<Self> {
         let notes_dir = config.notes_dir.clone();
         fs::create_dir_all(&notes_dir)?;
         let db = Database::new(config.db_path.clone())?;
-        let crypto = Crypto::new(&config.encryption_key);
-        Ok(Self {
+        rekey::recover(&mut config, &db)?;
+        let crypto = Self::open_crypto(&config, &db)?;
+        let private_metadata = db.get_meta("private_metadata")?.as_deref() == Some("1");
+        let mut manager = Self {
             config,
             db,
             crypto,
             notes_dir,
-        })
+            private_metadata,
+            recovered_edits: Vec::new(),
+            unplaced_edits: Vec::new(),
+        };
+        if manager.db.get_meta("notes_bound")?.is_none() {
+            manager.bind_legacy_notes()?;
+        }
+        manager.recovered_edits = manager.recover_orphaned_edits()?;
+        if manager.db.get_meta("tags_indexed")?.is_none() {
+            manager.index_tags()?;
+        }
+        if manager.db.get_meta("links_indexed")?.is_none() {
+            manager.index_links()?;
+        }
+        Ok(manager)
+    }
+
+    pub fn recovered_edits(&self) -> &[(i64, i64)] {
+        &self.recovered_edits
     }
 
-    pub fn create_note(&self, title: &str) -> Result<()> {
+    pub fn unplaced_edits(&self) -> &[PathBuf] {
+        &self.unplaced_edits
+    }
+
+    /// Saves plaintext left behind by editing sessions that were killed or
+    /// crashed as a new revision of its note, then wipes it. This includes
+    /// `.temp` files that older versions staged next to the encrypted notes.
+    /// Staged plaintext of notes this vault does not know is left in place
+    /// and listed in [`NotesManager::unplaced_edits`].
+    fn recover_orphaned_edits(&mut self) -> Result<Vec<(i64, i64)>> {
+        let mut recovered = Vec::new();
+        for orphan in staging::orphans(&self.config.db_path)? {
+            match self.db.get_note_by_uuid(&orphan.note_uuid)? {
+                Some(note) => {
+                    recovered.extend(self.recover_edit(&note, &orphan.read()?)?);
+                    orphan.remove()?;
+                }
+                // Never throw away unsaved text we cannot place.
+                None => {
+                    warn!("Keeping staged edit of unknown note {}", orphan.note_uuid);
+                    self.unplaced_edits.push(orphan.path);
+                }
+            }
+        }
+
+        for note in self.db.get_all_notes()? {
+            let temp_path = self.notes_dir.join(&note.filename).with_extension("temp");
+            if temp_path.exists() {
+                let content = Zeroizing::new(fs::read(&temp_path)?);
+                recovered.extend(self.recover_edit(&note, &content)?);
+                staging::wipe(&temp_path)?;
+            }
+        }
+        Ok(recovered)
+    }
+
+    fn recover_edit(&self, note: &NoteRecord, content: &[u8]) -> Result<Option<(i64, i64)>> {
+        let current = self.note_content(note)?;
+        if content == current.as_slice() {
+            return Ok(None);
+        }
+        let rev = self.record_revision(note, &current, content)?;
+        warn!("Recovered unsaved edits of note {} as revision {}", note.id, rev);
+        Ok(Some((note.id, rev)))
+    }
+
+    /// Builds the vault cipher. Vaults with Argon2id parameters stored in the
+    /// database are unlocked with the master passphrase; everything else uses
+    /// the legacy SHA-256 of `encryption_key`.
+    fn open_crypto(config: &Config, db: &Database) -> Result<Crypto> {
+        match db.get_meta("kdf")?.as_deref() {
+            Some("argon2id") => {
+                let salt = db
+                    .get_meta("kdf_salt")?
+                    .ok_or_else(|| NoterError::Config("Missing KDF salt".to_string()))?;
+                let salt = BASE64
+                    .decode(salt)
+                    .map_err(|e| NoterError::Config(format!("Invalid KDF salt: {}", e)))?;
+                let params = KdfParams {
+                    memory_kib: Self::meta_u32(db, "kdf_memory_kib")?,
+                    iterations: Self::meta_u32(db, "kdf_iterations")?,
+                    parallelism: Self::meta_u32(db, "kdf_parallelism")?,
+                };
+
+                let passphrase =
+                    crate::utils::get_passphrase(config.passphrase_command.as_deref(), false)?;
+                let crypto = Crypto::from_passphrase(&passphrase, &salt, &params)?;
+                if let Some(key_check) = db.get_meta("key_check")? {
+                    if key_check != crypto.key_check() {
+                        return Err(NoterError::InvalidPassphrase);
+                    }
+                }
+                Ok(crypto)
+            }
+            Some(other) => Err(NoterError::Config(format!("Unknown KDF: {}", other))),
+            None if config.use_passphrase => {
+                if db.count_notes()? > 0 {
+                    return Err(NoterError::Config(
+                        "use_passphrase cannot be enabled on a vault that already has notes"
+                            .to_string(),
+                    ));
+                }
+
+                let passphrase =
+                    crate::utils::get_passphrase(config.passphrase_command.as_deref(), true)?;
+                let salt = Crypto::generate_salt();
+                let crypto = Crypto::from_passphrase(&passphrase, &salt, &config.kdf)?;
+                let meta = Self::kdf_meta(&salt, &config.kdf, &crypto);
+                db.set_meta(&Self::meta_refs(&meta))?;
+                info!("Initialised passphrase-protected vault");
+                Ok(crypto)
+            }
+            None => Ok(Crypto::new(&config.encryption_key)),
+        }
+    }
+
+    fn kdf_meta(salt: &[u8], params: &KdfParams, crypto: &Crypto) -> Vec<(&'static str, String)> {
+        vec![
+            ("kdf", "argon2id".to_string()),
+            ("kdf_salt", BASE64.encode(salt)),
+            ("kdf_memory_kib", params.memory_kib.to_string()),
+            ("kdf_iterations", params.iterations.to_string()),
+            ("kdf_parallelism", params.parallelism.to_string()),
+            ("key_check", crypto.key_check().to_string()),
+        ]
+    }
+
+    fn meta_refs<'a>(meta: &'a [(&'static str, String)]) -> Vec<(&'a str, &'a str)> {
+        meta.iter().map(|(k, v)| (*k, v.as_str())).collect()
+    }
+
+    fn meta_u32(db: &Database, key: &str) -> Result<u32> {
+        db.get_meta(key)?
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| NoterError::Config(format!("Missing or invalid {}", key)))
+    }
+
+    /// Creates a note with `body` below its front matter and returns its ID.
+    pub fn create_note(&self, title: &str, body: &str) -> Result<i64> {
         if title.trim().is_empty() {
             return Err(NoterError::InvalidTitle("Title cannot be empty".to_string()));
         }
 
-        let filename = self.format_filename(title);
+        let uuid = Uuid::new_v4().to_string();
+        let filename = self.unique_filename(title, Local::now());
+        let stored_title = self.seal_title(&self.crypto, &uuid, title)?;
         let file_path = self.notes_dir.join(&filename);
 
-        let content = format!(
-            "---\ntitle: {}\ndate: {}\n---\n\n",
-            title,
-            Local::now().format("%Y-%m-%d %H:%M:%S")
-        );
+        let mut metadata = NoteMetadata::new(title, Local::now());
+        metadata.uuid = Some(uuid.clone());
+        let mut content = Zeroizing::new(metadata.render(body));
+        if !body.is_empty() && !body.ends_with('\n') {
+            content.push('\n');
+        }
 
         let encrypted = self
             .crypto
-            .encrypt(content.as_bytes())
-            .map_err(|e| NoterError::Encryption(e.to_string()))?;
+            .encrypt_bound(content.as_bytes(), Self::binding(&uuid, &filename))?;
         fs::write(&file_path, encrypted)?;
 
-        self.db.insert_note(title, &filename)?;
+        let id = self.db.insert_note(&uuid, &stored_title, &filename)?;
+        self.sync_links(id, &uuid, content.as_bytes())?;
         info!("Created encrypted note: {} at {:?}", title, file_path);
 
-        Ok(())
+        Ok(id)
     }
 
-    pub fn read_note(&self, id: i64) -> Result<String> {
-        let note = self.db.get_note(id)?.ok_or_else(|| NoterError::NoteNotFound(id))?;
-        let file_path = self.notes_dir.join(&note.filename);
-        let encrypted = fs::read_to_string(file_path)?;
-        let decrypted = self
-            .crypto
-            .decrypt(&encrypted)
-            .map_err(|e| NoterError::Encryption(e.to_string()))?;
-        String::from_utf8(decrypted).map_err(|e| NoterError::Encryption(e.to_string()))
+    pub fn read_note(&self, id: i64) -> Result<Zeroizing<String>> {
+        let note = self.db.get_note(id)?.ok_or(NoterError::NoteNotFound(id))?;
+        crypto::into_string(self.note_content(&note)?)
     }
 
     pub fn edit_note(&self, id: i64) -> Result<()> {
-        let note = self.db.get_note(id)?.ok_or_else(|| NoterError::NoteNotFound(id))?;
+        let note = self.db.get_note(id)?.ok_or(NoterError::NoteNotFound(id))?;
         let file_path = self.notes_dir.join(&note.filename);
 
-        let encrypted_content = fs::read_to_string(&file_path)?;
-        let decrypted_content = self.crypto
-            .decrypt(&encrypted_content)
-            .map_err(|e| NoterError::Encryption(e.to_string()))?;
-
-        let temp_path = file_path.with_extension("temp");
-        fs::write(&temp_path, &decrypted_content)?;
+        let decrypted_content = self.note_content(&note)?;
 
         let editor = self.config.editor.clone()
             .or_else(|| std::env::var("EDITOR").ok())
-            .ok_or_else(|| NoterError::EditorNotFound)?;
+            .ok_or(NoterError::EditorNotFound)?;
+
+        let extension = file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or(&self.config.default_extension);
+        let staged = PlaintextFile::create(&self.config.db_path, &note.uuid, extension, &decrypted_content)?;
 
         let status = std::process::Command::new(editor)
-            .arg(&temp_path)
+            .arg(staged.path())
             .status()
             .map_err(|e| NoterError::EditorError(e.to_string()))?;
 
         if !status.success() {
-            fs::remove_file(&temp_path)?;
             return Err(NoterError::EditorError("Editor exited with non-zero status".to_string()));
         }
 
-        let modified_content = fs::read(&temp_path)?;
+        // A UUID removed or changed by hand is put back.
+        let modified_content = Self::with_uuid(&note, staged.read()?);
+        if let Err(e) = self.write_note_file(&note, &modified_content) {
+            // Leave the plaintext staged so the next start recovers it.
+            std::mem::forget(staged);
+            return Err(e);
+        }
+        drop(staged);
 
-        let encrypted = self.crypto
-            .encrypt(&modified_content)
-            .map_err(|e| NoterError::Encryption(e.to_string()))?;
+        if self.touch_if_changed(&note, &decrypted_content, &modified_content)? {
+            self.record_revision(&note, &decrypted_content, &modified_content)?;
+        }
 
-        fs::write(&file_path, encrypted)?;
+        let metadata = NoteMetadata::from_content(&String::from_utf8_lossy(&modified_content));
+        self.sync_metadata(&note, &metadata)?;
+        self.sync_links(note.id, &note.uuid, &modified_content)?;
+
+        Ok(())
+    }
 
-        fs::remove_file(&temp_path)?;
+    /// Applies front matter edited by hand to the database: a changed
+    /// title is renamed in the `notes` table and tags are re-indexed.
+    fn sync_metadata(&self, note: &NoteRecord, metadata: &NoteMetadata) -> Result<()> {
+        let title = metadata.title.as_deref().map(str::trim).unwrap_or("");
+        if !title.is_empty() && title != self.open_title(&note.uuid, &note.title)?.as_str() {
+            let stored = self.seal_title(&self.crypto, &note.uuid, title)?;
+            self.db.update_title(note.id, &stored)?;
+            info!("Renamed note {} to '{}'", note.id, title);
+        }
+        self.sync_tags(note.id, &metadata.tags)
+    }
+
+    /// Bumps the note's `updated_at` if `new` differs from `previous`, going
+    /// by content hash so that saving an unchanged note keeps its date.
+    /// Returns whether the content changed.
+    fn touch_if_changed(&self, note: &NoteRecord, previous: &[u8], new: &[u8]) -> Result<bool> {
+        if crypto::sha256_hex(previous) == crypto::sha256_hex(new) {
+            return Ok(false);
+        }
+        self.db.set_updated_at(note.id, Local::now())?;
+        Ok(true)
+    }
 
+    /// Snapshots `new` as the latest revision. The first time a note changes,
+    /// the content it had before is saved as revision 1.
+    fn record_revision(&self, note: &NoteRecord, previous: &[u8], new: &[u8]) -> Result<i64> {
+        if self.db.get_revisions(Some(note.id))?.is_empty() {
+            let snapshot = self.crypto.encrypt_bound(previous, Self::revision_binding(note))?;
+            self.db.insert_revision(note.id, &snapshot)?;
+        }
+        let snapshot = self.crypto.encrypt_bound(new, Self::revision_binding(note))?;
+        self.db.insert_revision(note.id, &snapshot)
+    }
+
+    fn revision_binding(note: &NoteRecord) -> Binding<'_> {
+        Binding {
+            id: &note.uuid,
+            context: "revision",
+        }
+    }
+
+    fn open_revision(&self, note: &NoteRecord, revision: RevisionRecord) -> Result<Revision> {
+        let content = self
+            .crypto
+            .decrypt_bound(&revision.content, Self::revision_binding(note))?;
+        Ok(Revision {
+            rev: revision.rev,
+            created_at: revision.created_at,
+            content: crypto::into_string(content)?,
+        })
+    }
+
+    /// Saved revisions of a note with decrypted content, oldest first.
+    pub fn history(&self, id: i64) -> Result<Vec<Revision>> {
+        let note = self.db.get_note(id)?.ok_or(NoterError::NoteNotFound(id))?;
+        self.db
+            .get_revisions(Some(id))?
+            .into_iter()
+            .map(|revision| self.open_revision(&note, revision))
+            .collect()
+    }
+
+    fn revision_content(&self, note: &NoteRecord, rev: i64) -> Result<Zeroizing<String>> {
+        let revision = self
+            .db
+            .get_revision(note.id, rev)?
+            .ok_or(NoterError::RevisionNotFound(note.id, rev))?;
+        Ok(self.open_revision(note, revision)?.content)
+    }
+
+    /// Unified diff from revision `from` to revision `to`, or to the current
+    /// content when `to` is `None`.
+    pub fn diff_revisions(&self, id: i64, from: i64, to: Option<i64>) -> Result<String> {
+        let note = self.db.get_note(id)?.ok_or(NoterError::NoteNotFound(id))?;
+        let old = self.revision_content(&note, from)?;
+        let (new, new_label) = match to {
+            Some(to) => (self.revision_content(&note, to)?, format!("rev {}", to)),
+            None => (self.read_note(id)?, "current".to_string()),
+        };
+
+        Ok(TextDiff::from_lines(old.as_str(), new.as_str())
+            .unified_diff()
+            .header(&format!("rev {}", from), &new_label)
+            .to_string())
+    }
+
+    /// Changes a note's title in its front matter and the database. The
+    /// note keeps its filename.
+    pub fn rename_note(&self, id: i64, title: &str) -> Result<()> {
+        let title = title.trim();
+        if title.is_empty() {
+            return Err(NoterError::InvalidTitle("Title cannot be empty".to_string()));
+        }
+        self.update_metadata(id, |metadata| metadata.title = Some(title.to_string()))
+    }
+
+    /// Replaces a note's tags.
+    pub fn set_tags(&self, id: i64, tags: &[String]) -> Result<()> {
+        let mut names: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let tag = tag.trim();
+            if tag.is_empty() || tag.contains([',', '[', ']', '\n']) {
+                return Err(NoterError::InvalidInput(format!("Invalid tag name: {:?}", tag)));
+            }
+            if !names.iter().any(|name| name == tag) {
+                names.push(tag.to_string());
+            }
+        }
+        self.update_metadata(id, |metadata| metadata.tags = names)
+    }
+
+    /// Rewrites a note's front matter with `update` applied and syncs the
+    /// result to the database.
+    fn update_metadata(&self, id: i64, update: impl FnOnce(&mut NoteMetadata)) -> Result<()> {
+        let note = self.db.get_note(id)?.ok_or(NoterError::NoteNotFound(id))?;
+        let content = crypto::into_string(self.note_content(&note)?)?;
+
+        let (front, body) = frontmatter::split(&content);
+        let mut metadata = front.map(NoteMetadata::parse).unwrap_or_default();
+        update(&mut metadata);
+        metadata.uuid = Some(note.uuid.clone());
+        let updated = Zeroizing::new(metadata.render(body));
+
+        self.write_note_file(&note, updated.as_bytes())?;
+        if self.touch_if_changed(&note, content.as_bytes(), updated.as_bytes())? {
+            self.record_revision(&note, content.as_bytes(), updated.as_bytes())?;
+        }
+        self.sync_metadata(&note, &metadata)
+    }
+
+    /// Adds `text` to the end of a note, on a line of its own.
+    pub fn append_note(&self, id: i64, text: &str) -> Result<()> {
+        let note = self.db.get_note(id)?.ok_or(NoterError::NoteNotFound(id))?;
+        let current = self.note_content(&note)?;
+
+        let mut updated = Zeroizing::new(Vec::with_capacity(current.len() + text.len() + 2));
+        updated.extend_from_slice(&current);
+        if !updated.is_empty() && !updated.ends_with(b"\n") {
+            updated.push(b'\n');
+        }
+        updated.extend_from_slice(text.as_bytes());
+        if !updated.ends_with(b"\n") {
+            updated.push(b'\n');
+        }
+
+        self.write_note_file(&note, &updated)?;
+        if self.touch_if_changed(&note, &current, &updated)? {
+            self.record_revision(&note, &current, &updated)?;
+        }
+        self.sync_links(note.id, &note.uuid, &updated)?;
+        info!("Appended {} bytes to note {}", text.len(), id);
+        Ok(())
+    }
+
+    /// Replaces the note's content with revision `rev`. The restored
+    /// content is itself recorded as a new revision.
+    pub fn restore_revision(&self, id: i64, rev: i64) -> Result<()> {
+        let note = self.db.get_note(id)?.ok_or(NoterError::NoteNotFound(id))?;
+        let restored = self.revision_content(&note, rev)?;
+        let restored = match frontmatter::with_uuid(&restored, &note.uuid) {
+            Some(content) => Zeroizing::new(content),
+            None => restored,
+        };
+        let current = self.note_content(&note)?;
+        if current.as_slice() == restored.as_bytes() {
+            return Ok(());
+        }
+
+        self.write_note_file(&note, restored.as_bytes())?;
+        self.touch_if_changed(&note, &current, restored.as_bytes())?;
+        self.record_revision(&note, &current, restored.as_bytes())?;
+        self.sync_metadata(&note, &NoteMetadata::from_content(&restored))?;
+        self.sync_links(note.id, &note.uuid, restored.as_bytes())?;
+        info!("Restored note {} to revision {}", id, rev);
         Ok(())
     }
 
+    /// Note bodies are bound to the note's UUID and filename, so a file
+    /// moved or copied over another note's file fails to decrypt.
+    fn binding<'a>(uuid: &'a str, filename: &'a str) -> Binding<'a> {
+        Binding {
+            id: uuid,
+            context: filename,
+        }
+    }
+
+    fn write_note_file(&self, note: &NoteRecord, content: &[u8]) -> Result<()> {
+        let encrypted = self
+            .crypto
+            .encrypt_bound(content, Self::binding(&note.uuid, &note.filename))?;
+        fs::write(self.notes_dir.join(&note.filename), encrypted)?;
+        Ok(())
+    }
+
+    /// The note as users see it: decrypted, with its UUID in the front
+    /// matter even if the stored file predates UUIDs there.
+    fn note_content(&self, note: &NoteRecord) -> Result<Zeroizing<Vec<u8>>> {
+        Ok(Self::with_uuid(note, self.decrypt_note_file(note)?))
+    }
+
+    fn with_uuid(note: &NoteRecord, content: Zeroizing<Vec<u8>>) -> Zeroizing<Vec<u8>> {
+        let updated = std::str::from_utf8(&content)
+            .ok()
+            .and_then(|text| frontmatter::with_uuid(text, &note.uuid));
+        match updated {
+            Some(updated) => Zeroizing::new(updated.into_bytes()),
+            None => content,
+        }
+    }
+
+    fn decrypt_note_file(&self, note: &NoteRecord) -> Result<Zeroizing<Vec<u8>>> {
+        let encrypted = fs::read_to_string(self.notes_dir.join(&note.filename))?;
+        self.crypto
+            .decrypt_bound(&encrypted, Self::binding(&note.uuid, &note.filename))
+    }
+
+    /// With private metadata, values stored in the database are encrypted
+    /// under `binding`; otherwise they are stored as-is.
+    fn seal_meta(&self, crypto: &Crypto, value: &str, binding: Binding) -> Result<String> {
+        if !self.private_metadata {
+            return Ok(value.to_string());
+        }
+        crypto.encrypt_bound(value.as_bytes(), binding)
+    }
+
+    fn open_meta(&self, stored: &str, binding: Binding) -> Result<Zeroizing<String>> {
+        if !self.private_metadata {
+            return Ok(Zeroizing::new(stored.to_string()));
+        }
+        crypto::into_string(self.crypto.decrypt_bound(stored, binding)?)
+    }
+
+    /// Titles are bound to their note's UUID like note bodies are.
+    fn seal_title(&self, crypto: &Crypto, uuid: &str, title: &str) -> Result<String> {
+        self.seal_meta(crypto, title, Binding { id: uuid, context: "title" })
+    }
+
+    fn open_title(&self, uuid: &str, stored: &str) -> Result<Zeroizing<String>> {
+        self.open_meta(stored, Binding { id: uuid, context: "title" })
+    }
+
+    fn open_record(&self, mut note: NoteRecord) -> Result<NoteRecord> {
+        note.title = self.open_title(&note.uuid, &note.title)?;
+        Ok(note)
+    }
+
+    pub fn get_note(&self, id: i64) -> Result<NoteRecord> {
+        let note = self.db.get_note(id)?.ok_or(NoterError::NoteNotFound(id))?;
+        self.open_record(note)
+    }
+
+    /// Notes matching `query`, best first. A query that parses as a note ID
+    /// or UUID matches only that note, and fails if there is none. Otherwise
+    /// titles equal to the query (ignoring case) win, then titles containing
+    /// it, then fuzzy matches; only the best of these groups is returned.
+    pub fn find_notes(&self, query: &str) -> Result<NoteMatches> {
+        let query = query.trim();
+        if let Ok(id) = query.parse::<i64>() {
+            let note = self.get_note(id)?;
+            return Ok(NoteMatches { notes: vec![note], exact: true });
+        }
+        if let Ok(uuid) = Uuid::parse_str(query) {
+            let note = self
+                .db
+                .get_note_by_uuid(&uuid.to_string())?
+                .ok_or_else(|| NoterError::NoMatchingNote(query.to_string()))?;
+            return Ok(NoteMatches { notes: vec![self.open_record(note)?], exact: true });
+        }
+
+        let lower = query.to_lowercase();
+        let notes = self.list_notes()?;
+        let exact: Vec<NoteRecord> = notes
+            .iter()
+            .filter(|note| note.title.to_lowercase() == lower)
+            .cloned()
+            .collect();
+        if !exact.is_empty() {
+            return Ok(NoteMatches { notes: exact, exact: true });
+        }
+
+        let mut ranked: Vec<(bool, i64, NoteRecord)> = notes
+            .into_iter()
+            .filter_map(|note| {
+                let score = fuzzy::score(query, &note.title)?;
+                Some((note.title.to_lowercase().contains(&lower), score, note))
+            })
+            .collect();
+        if ranked.iter().any(|(contains, ..)| *contains) {
+            ranked.retain(|(contains, ..)| *contains);
+        }
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.updated_at.cmp(&a.2.updated_at)));
+        let notes = ranked.into_iter().map(|(.., note)| note).collect();
+        Ok(NoteMatches { notes, exact: false })
+    }
+
     pub fn list_notes(&self) -> Result<Vec<NoteRecord>> {
-        self.db.get_all_notes()
+        self.db
+            .get_all_notes()?
+            .into_iter()
+            .map(|note| self.open_record(note))
+            .collect()
     }
 
+    /// With private metadata, titles can only be matched after decryption,
+    /// so the search runs in memory.
     pub fn search_notes(&self, query: &str) -> Result<Vec<NoteRecord>> {
-        self.db.search_notes(query)
+        if !self.private_metadata {
+            return self.db.search_notes(query);
+        }
+
+        let query = query.to_lowercase();
+        Ok(self
+            .list_notes()?
+            .into_iter()
+            .filter(|note| note.title.to_lowercase().contains(&query))
+            .collect())
+    }
+
+    /// Full-text search over titles and decrypted note bodies. Notes that
+    /// fail to decrypt are skipped with a warning.
+    pub fn search_content(&self, query: &str) -> Result<Vec<SearchHit>> {
+        let query = Query::parse(query)?;
+        let mut index = SearchIndex::new();
+        for note in self.list_notes()? {
+            match self.decrypt_note_file(&note).and_then(crypto::into_string) {
+                Ok(content) => {
+                    let (front, body) = frontmatter::split(&content);
+                    let tags = front.map(NoteMetadata::parse).unwrap_or_default().tags;
+                    index.add(note, &tags, Zeroizing::new(body.to_string()));
+                }
+                Err(e) => warn!("Skipping note {} in search: {}", note.id, e),
+            }
+        }
+        Ok(index.search(&query))
+    }
+
+    /// Stores the tags declared in a note's front matter.
+    fn sync_tags(&self, note_id: i64, names: &[String]) -> Result<()> {
+        let mut tags = self.tags()?;
+        let mut tag_ids = Vec::with_capacity(names.len());
+        for name in names {
+            let id = match tags.iter().find(|(_, existing)| existing.as_str() == name.as_str()) {
+                Some((id, _)) => *id,
+                None => {
+                    let stored = self.seal_meta(&self.crypto, name, TAG_BINDING)?;
+                    let id = self.db.insert_tag(&stored)?;
+                    tags.push((id, Zeroizing::new(name.clone())));
+                    id
+                }
+            };
+            tag_ids.push(id);
+        }
+        self.db.set_note_tags(note_id, &tag_ids)
+    }
+
+    /// Indexes the front matter tags of every note, for vaults created
+    /// before tags were tracked. Notes that fail to decrypt are skipped with
+    /// a warning.
+    fn index_tags(&self) -> Result<()> {
+        for note in self.db.get_all_notes()? {
+            match self.decrypt_note_file(&note) {
+                Ok(content) => {
+                    let metadata = NoteMetadata::from_content(&String::from_utf8_lossy(&content));
+                    self.sync_tags(note.id, &metadata.tags)?;
+                }
+                Err(e) => warn!("Cannot index tags of note {}: {}", note.id, e),
+            }
+        }
+        self.db.set_meta(&[("tags_indexed", "1")])
+    }
+
+    /// All tags as `(id, name)`. Tag names are matched in memory because
+    /// with private metadata they are stored encrypted.
+    fn tags(&self) -> Result<Vec<(i64, Zeroizing<String>)>> {
+        self.db
+            .get_tags()?
+            .into_iter()
+            .map(|(id, stored)| Ok((id, self.open_meta(&stored, TAG_BINDING)?)))
+            .collect()
+    }
+
+    fn tag_id(&self, name: &str) -> Result<Option<i64>> {
+        Ok(self
+            .tags()?
+            .into_iter()
+            .find(|(_, existing)| existing.as_str() == name)
+            .map(|(id, _)| id))
+    }
+
+    /// Tag names with the number of notes using each, sorted by name.
+    pub fn tag_counts(&self) -> Result<Vec<(Zeroizing<String>, i64)>> {
+        let mut counts = self
+            .db
+            .get_tag_counts()?
+            .into_iter()
+            .map(|(_, stored, count)| Ok((self.open_meta(&stored, TAG_BINDING)?, count)))
+            .collect::<Result<Vec<_>>>()?;
+        counts.sort_by_key(|(name, _)| name.to_lowercase());
+        Ok(counts)
+    }
+
+    pub fn list_notes_with_tag(&self, tag: &str) -> Result<Vec<NoteRecord>> {
+        let Some(tag_id) = self.tag_id(tag)? else {
+            return Ok(Vec::new());
+        };
+        let ids = self.db.get_note_ids_with_tag(tag_id)?;
+        Ok(self
+            .list_notes()?
+            .into_iter()
+            .filter(|note| ids.contains(&note.id))
+            .collect())
+    }
+
+    /// Renames a tag in the front matter of every note using it. Renaming
+    /// onto an existing tag merges the two. Returns the number of notes
+    /// updated.
+    pub fn rename_tag(&self, from: &str, to: &str) -> Result<usize> {
+        self.merge_tags(&[from.to_string()], to)
+    }
+
+    /// Replaces each tag in `from` with `into` in every note using it.
+    /// Returns the number of notes updated.
+    pub fn merge_tags(&self, from: &[String], into: &str) -> Result<usize> {
+        let into = into.trim();
+        if into.is_empty() || into.contains([',', '[', ']', '\n']) {
+            return Err(NoterError::InvalidInput(format!("Invalid tag name: {:?}", into)));
+        }
+
+        let mut note_ids = Vec::new();
+        for name in from {
+            let tag_id = self
+                .tag_id(name)?
+                .ok_or_else(|| NoterError::InvalidInput(format!("Tag not found: {}", name)))?;
+            for id in self.db.get_note_ids_with_tag(tag_id)? {
+                if !note_ids.contains(&id) {
+                    note_ids.push(id);
+                }
+            }
+        }
+
+        for &id in &note_ids {
+            let note = self.db.get_note(id)?.ok_or(NoterError::NoteNotFound(id))?;
+            let content = crypto::into_string(self.note_content(&note)?)?;
+
+            let (front, body) = frontmatter::split(&content);
+            let mut metadata = front.map(NoteMetadata::parse).unwrap_or_default();
+            let mut tags = Vec::new();
+            for tag in metadata.tags {
+                let tag = if from.contains(&tag) { into.to_string() } else { tag };
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            metadata.tags = tags;
+
+            let updated = Zeroizing::new(metadata.render(body));
+            self.write_note_file(&note, updated.as_bytes())?;
+            if self.touch_if_changed(&note, content.as_bytes(), updated.as_bytes())? {
+                self.record_revision(&note, content.as_bytes(), updated.as_bytes())?;
+            }
+            self.sync_tags(note.id, &metadata.tags)?;
+        }
+
+        Ok(note_ids.len())
+    }
+
+    fn link_binding(uuid: &str) -> Binding<'_> {
+        Binding {
+            id: uuid,
+            context: "link",
+        }
+    }
+
+    /// Re-reads the wiki links of a note from its content.
+    fn sync_links(&self, note_id: i64, uuid: &str, content: &[u8]) -> Result<()> {
+        let content = String::from_utf8_lossy(content);
+        let stored = links::parse(frontmatter::body(&content))
+            .iter()
+            .map(|link| self.seal_meta(&self.crypto, &link.to_string(), Self::link_binding(uuid)))
+            .collect::<Result<Vec<_>>>()?;
+        self.db.set_note_links(note_id, &stored)
+    }
+
+    /// Indexes the links of every note, for vaults created before links
+    /// were tracked. Notes that fail to decrypt are skipped with a warning.
+    fn index_links(&self) -> Result<()> {
+        for note in self.db.get_all_notes()? {
+            match self.decrypt_note_file(&note) {
+                Ok(content) => self.sync_links(note.id, &note.uuid, &content)?,
+                Err(e) => warn!("Cannot index links of note {}: {}", note.id, e),
+            }
+        }
+        self.db.set_meta(&[("links_indexed", "1")])
+    }
+
+    /// Every stored link as `(link id, source note, link)`. `notes` must
+    /// include every note.
+    fn stored_links<'a>(&self, notes: &'a [NoteRecord]) -> Result<Vec<(i64, &'a NoteRecord, WikiLink)>> {
+        let mut links = Vec::new();
+        for (id, note_id, stored) in self.db.get_links()? {
+            let source = notes
+                .iter()
+                .find(|note| note.id == note_id)
+                .ok_or(NoterError::NoteNotFound(note_id))?;
+            let link = self.open_meta(&stored, Self::link_binding(&source.uuid))?;
+            links.push((id, source, WikiLink::parse(&link)));
+        }
+        Ok(links)
+    }
+
+    /// Every link in the vault with its target resolved: a UUID names the
+    /// note with that UUID, anything else the note with that title
+    /// (ignoring case, and preferring the oldest note if titles repeat).
+    fn resolved_links(&self) -> Result<Vec<NoteLink>> {
+        let notes = self.list_notes()?;
+        let mut by_id: Vec<&NoteRecord> = notes.iter().collect();
+        by_id.sort_by_key(|note| note.id);
+
+        Ok(self
+            .stored_links(&notes)?
+            .into_iter()
+            .map(|(_, source, link)| {
+                let target = link.note();
+                let note = match Uuid::parse_str(target) {
+                    Ok(uuid) => {
+                        let uuid = uuid.to_string();
+                        by_id.iter().find(|note| note.uuid == uuid)
+                    }
+                    Err(_) => {
+                        let target = target.to_lowercase();
+                        by_id.iter().find(|note| note.title.trim().to_lowercase() == target)
+                    }
+                };
+                NoteLink {
+                    source: source.clone(),
+                    note: note.map(|note| (*note).clone()),
+                    target: link.target,
+                    alias: link.alias,
+                }
+            })
+            .collect())
+    }
+
+    /// Links in note `id`, in the order they appear.
+    pub fn links(&self, id: i64) -> Result<Vec<NoteLink>> {
+        self.get_note(id)?;
+        let mut links = self.resolved_links()?;
+        links.retain(|link| link.source.id == id);
+        Ok(links)
+    }
+
+    /// Links from other notes (or itself) to note `id`.
+    pub fn backlinks(&self, id: i64) -> Result<Vec<NoteLink>> {
+        self.get_note(id)?;
+        let mut links = self.resolved_links()?;
+        links.retain(|link| link.note.as_ref().is_some_and(|note| note.id == id));
+        Ok(links)
+    }
+
+    /// Links whose target no longer exists, e.g. after a rename or delete.
+    pub fn broken_links(&self) -> Result<Vec<NoteLink>> {
+        let mut links = self.resolved_links()?;
+        links.retain(|link| link.note.is_none());
+        Ok(links)
+    }
+
+    pub fn private_metadata(&self) -> bool {
+        self.private_metadata
+    }
+
+    /// Migrates the vault to private metadata: titles are encrypted and every
+    /// note file is re-encrypted under a new opaque filename. New files are
+    /// written before the database switches over, so an interruption leaves
+    /// either the old or the new layout intact. Returns the number of notes
+    /// migrated.
+    pub fn enable_private_metadata(&mut self) -> Result<usize> {
+        if self.private_metadata {
+            return Ok(0);
+        }
+
+        let notes = self.db.get_all_notes()?;
+        let tags = self.tags()?;
+        let links = self.stored_links(&notes)?;
+        let mut rewritten = Vec::with_capacity(notes.len());
+        let mut written = Vec::with_capacity(notes.len());
+        self.private_metadata = true;
+
+        let staged = notes.iter().try_for_each(|note| {
+            let filename = self.opaque_filename();
+            let content = self.decrypt_note_file(note)?;
+            let encrypted = self
+                .crypto
+                .encrypt_bound(&content, Self::binding(&note.uuid, &filename))?;
+            let path = self.notes_dir.join(&filename);
+            fs::write(&path, encrypted)?;
+            written.push(path);
+
+            let title = self.seal_title(&self.crypto, &note.uuid, &note.title)?;
+            rewritten.push((note.id, title, filename));
+            Ok(())
+        });
+
+        let committed = staged
+            .and_then(|_| {
+                tags.iter()
+                    .map(|(id, name)| Ok((*id, self.seal_meta(&self.crypto, name, TAG_BINDING)?)))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .and_then(|tags| {
+                let links = links
+                    .iter()
+                    .map(|(id, source, link)| {
+                        let binding = Self::link_binding(&source.uuid);
+                        Ok((*id, self.seal_meta(&self.crypto, &link.to_string(), binding)?))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                self.db
+                    .rewrite_notes(&rewritten, &tags, &links, &[("private_metadata", "1")])
+            });
+        if let Err(e) = committed {
+            self.private_metadata = false;
+            for path in written {
+                let _ = fs::remove_file(path);
+            }
+            return Err(e);
+        }
+
+        for note in &notes {
+            let old_path = self.notes_dir.join(&note.filename);
+            if let Err(e) = fs::remove_file(&old_path) {
+                warn!("Failed to remove old note file {}: {}", old_path.display(), e);
+            }
+        }
+
+        info!("Enabled private metadata for {} notes", notes.len());
+        Ok(notes.len())
     }
 
     pub fn delete_note(&self, id: i64) -> Result<bool> {
@@ -126,37 +1043,452 @@ impl NotesManager {
         }
     }
 
-    pub fn export_notes(&self, export_dir: Option<&Path>) -> Result<(usize, usize)> {
-        let notes = self.list_notes()?;
-        let total_count = notes.len();
-        if total_count == 0 {
-            return Ok((0, 0));
+    /// Imports every `.md` and `.txt` file under `dir`, recursing into
+    /// subdirectories and skipping hidden entries. Titles, dates and tags
+    /// are taken from front matter when present; otherwise the file name
+    /// and modification time are used and written into new front matter.
+    /// Files identical to an existing note are reported as duplicates.
+    pub fn import_dir(&self, dir: &Path) -> Result<ImportReport> {
+        if !dir.is_dir() {
+            return Err(NoterError::InvalidInput(format!(
+                "{} is not a directory",
+                dir.display()
+            )));
+        }
+
+        // Exports carry the UUID in their front matter even when the stored
+        // note does not, so both forms count as the same note.
+        let mut known = HashMap::new();
+        for note in self.db.get_all_notes()? {
+            match self.decrypt_note_file(&note) {
+                Ok(content) => {
+                    known.insert(Sha256::digest(&content), note.id);
+                    known.insert(Sha256::digest(Self::with_uuid(&note, content)), note.id);
+                }
+                Err(e) => warn!("Cannot check note {} for duplicates: {}", note.id, e),
+            }
+        }
+
+        let mut files = Vec::new();
+        Self::collect_importable(dir, &mut files)?;
+        files.sort();
+
+        let mut report = ImportReport::default();
+        for path in files {
+            let content = match fs::read(&path) {
+                Ok(content) => Zeroizing::new(content),
+                Err(e) => {
+                    report.failed.push((path, e.into()));
+                    continue;
+                }
+            };
+            let hash = Sha256::digest(&content);
+            if let Some(&id) = known.get(&hash) {
+                report.duplicates.push((path, id));
+                continue;
+            }
+            match self.import_file(&path, content) {
+                Ok(id) => {
+                    known.insert(hash, id);
+                    report.imported.push((path, id));
+                }
+                Err(e) => {
+                    warn!("Failed to import {}: {}", path.display(), e);
+                    report.failed.push((path, e));
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    fn collect_importable(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+            if path.is_dir() {
+                Self::collect_importable(&path, files)?;
+            } else if path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("txt")
+            }) {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn import_file(&self, path: &Path, content: Zeroizing<Vec<u8>>) -> Result<i64> {
+        let content = crypto::into_string(content)
+            .map_err(|_| NoterError::InvalidInput("File is not valid UTF-8".to_string()))?;
+        let (front, body) = frontmatter::split(&content);
+        let mut metadata = front.map(NoteMetadata::parse).unwrap_or_default();
+
+        let modified: DateTime<Local> = fs::metadata(path)?.modified()?.into();
+        let title = match metadata.title.as_deref().map(str::trim) {
+            Some(title) if !title.is_empty() => title.to_string(),
+            _ => path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().trim().to_string())
+                .filter(|stem| !stem.is_empty())
+                .ok_or_else(|| NoterError::InvalidTitle("File has no usable title".to_string()))?,
+        };
+        let created = metadata.created().unwrap_or(modified);
+        let updated = metadata.updated().unwrap_or(modified).max(created);
+
+        // A note exported from another vault keeps its UUID, unless a
+        // different note here already has it.
+        let uuid = match metadata.uuid.as_deref().and_then(|uuid| Uuid::parse_str(uuid.trim()).ok()) {
+            Some(uuid) if self.db.get_note_by_uuid(&uuid.to_string())?.is_none() => uuid.to_string(),
+            Some(uuid) => {
+                warn!("UUID {} of {} is already in use, assigning a new one", uuid, path.display());
+                Uuid::new_v4().to_string()
+            }
+            None => Uuid::new_v4().to_string(),
+        };
+
+        // Notes written by noters always carry a title, UUID and date; add
+        // them when the source file lacks them, keeping everything else as-is.
+        let content = if metadata.title.is_none()
+            || metadata.date.is_none()
+            || metadata.uuid.as_deref() != Some(uuid.as_str())
+        {
+            metadata.title.get_or_insert_with(|| title.clone());
+            metadata.uuid = Some(uuid.clone());
+            metadata
+                .date
+                .get_or_insert_with(|| created.format(frontmatter::DATE_FORMAT).to_string());
+            Zeroizing::new(metadata.render(body))
+        } else {
+            content
+        };
+
+        let filename = self.unique_filename(&title, created);
+        let stored_title = self.seal_title(&self.crypto, &uuid, &title)?;
+        let encrypted = self
+            .crypto
+            .encrypt_bound(content.as_bytes(), Self::binding(&uuid, &filename))?;
+        fs::write(self.notes_dir.join(&filename), encrypted)?;
+
+        let id = self
+            .db
+            .insert_note_at(&uuid, &stored_title, &filename, created, updated)?;
+        self.sync_tags(id, &metadata.tags)?;
+        self.sync_links(id, &uuid, content.as_bytes())?;
+        info!("Imported {} as note {}", path.display(), id);
+        Ok(id)
+    }
+
+    /// Writes an encrypted archive of the database and every note file to
+    /// `path` (see [`crate::backup`]). The database is copied with SQLite's
+    /// online backup API and the note list is read from that copy, so the
+    /// archive is consistent. Returns the number of notes archived.
+    pub fn backup(&self, path: &Path, passphrase: &str) -> Result<usize> {
+        let snapshot = path.with_extension("db.tmp");
+        self.db.backup_to(&snapshot)?;
+        let read_snapshot = || -> Result<(Vec<u8>, i64, Vec<NoteRecord>)> {
+            let db = Database::open(snapshot.clone())?;
+            Ok((fs::read(&snapshot)?, db.schema_version()?, db.get_all_notes()?))
+        };
+        let result = read_snapshot();
+        fs::remove_file(&snapshot)?;
+        let (db_bytes, schema_version, notes) = result?;
+
+        let key_source = self.key_source()?;
+        let mut archive = ArchiveWriter::new(
+            schema_version,
+            key_source == KeySource::Passphrase,
+            (key_source == KeySource::Config).then_some(self.config.encryption_key.as_str()),
+        );
+        archive.add(backup::DB_ENTRY, &db_bytes);
+        for note in &notes {
+            let contents = fs::read(self.notes_dir.join(&note.filename))?;
+            archive.add(&format!("{}{}", backup::NOTES_PREFIX, note.filename), &contents);
+        }
+        archive.finish(path, passphrase, &self.config.kdf)?;
+        info!("Backed up {} notes to {}", notes.len(), path.display());
+        Ok(notes.len())
+    }
+
+    pub fn key_source(&self) -> Result<KeySource> {
+        Ok(match self.db.get_meta("kdf")? {
+            Some(_) => KeySource::Passphrase,
+            None => KeySource::Config,
+        })
+    }
+
+    /// Re-encrypts every note under a new key. New ciphertexts are staged
+    /// first; nothing is touched until all notes have been re-encrypted, and
+    /// the switch-over is recoverable if interrupted (see [`crate::rekey`]).
+    /// Returns the number of notes re-encrypted.
+    pub fn rekey(&mut self, target: KeySource) -> Result<usize> {
+        let (crypto, meta, journal) = match target {
+            KeySource::Config => {
+                let key = Config::generate_encryption_key();
+                let crypto = Crypto::new(&key);
+                let meta = vec![("key_check", crypto.key_check().to_string())];
+                let journal = RekeyJournal {
+                    key_check: crypto.key_check().to_string(),
+                    use_passphrase: false,
+                    encryption_key: Some(key.to_string()),
+                };
+                (crypto, meta, journal)
+            }
+            KeySource::Passphrase => {
+                let passphrase = crate::utils::get_new_passphrase()?;
+                let salt = Crypto::generate_salt();
+                let crypto = Crypto::from_passphrase(&passphrase, &salt, &self.config.kdf)?;
+                let meta = Self::kdf_meta(&salt, &self.config.kdf, &crypto);
+                let journal = RekeyJournal {
+                    key_check: crypto.key_check().to_string(),
+                    use_passphrase: true,
+                    encryption_key: None,
+                };
+                (crypto, meta, journal)
+            }
+        };
+
+        let staging = rekey::staging_dir(&self.notes_dir);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+
+        let notes = self.db.get_all_notes()?;
+        let columns = match self.stage_rekeyed_notes(&notes, &crypto, &staging) {
+            Ok(columns) => columns,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&staging);
+                return Err(e);
+            }
+        };
+
+        rekey::write_journal(&staging, &journal)?;
+        self.db.commit_rekey(&Self::meta_refs(&meta), &columns)?;
+        rekey::complete(&staging, &self.notes_dir, &mut self.config)?;
+
+        self.crypto = crypto;
+        info!("Re-encrypted {} notes with a new key", notes.len());
+        Ok(notes.len())
+    }
+
+    /// Re-encrypts note files written before ciphertexts were bound to their
+    /// note, so [`Crypto::decrypt_bound`] can refuse unbound data from then
+    /// on. Uses the same staging and journal as [`NotesManager::rekey`],
+    /// with the current key. Notes that cannot be decrypted are left as they
+    /// are with a warning.
+    fn bind_legacy_notes(&mut self) -> Result<()> {
+        let staging = rekey::staging_dir(&self.notes_dir);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+
+        let staged = self.stage_bound_notes(&staging).and_then(|count| {
+            if count > 0 {
+                rekey::write_journal(
+                    &staging,
+                    &RekeyJournal {
+                        key_check: self.crypto.key_check().to_string(),
+                        use_passphrase: self.config.use_passphrase,
+                        encryption_key: None,
+                    },
+                )?;
+            }
+            Ok(count)
+        });
+        let count = match staged {
+            Ok(count) => count,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&staging);
+                return Err(e);
+            }
+        };
+
+        self.db.set_meta(&[
+            ("key_check", self.crypto.key_check()),
+            ("notes_bound", "1"),
+        ])?;
+        if count > 0 {
+            rekey::complete(&staging, &self.notes_dir, &mut self.config)?;
+            info!("Bound {} note files to their notes", count);
+        } else {
+            fs::remove_dir_all(&staging)?;
+        }
+        Ok(())
+    }
+
+    fn stage_bound_notes(&self, staging: &Path) -> Result<usize> {
+        let mut count = 0;
+        for note in self.db.get_all_notes()? {
+            let binding = Self::binding(&note.uuid, &note.filename);
+            let encrypted = match fs::read_to_string(self.notes_dir.join(&note.filename)) {
+                Ok(encrypted) => encrypted,
+                Err(e) => {
+                    warn!("Cannot read note {}: {}", note.id, e);
+                    continue;
+                }
+            };
+            if self.crypto.decrypt_bound(&encrypted, binding).is_ok() {
+                continue;
+            }
+            let content = match self.crypto.decrypt_for_upgrade(&encrypted, binding) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!("Cannot bind note {} to its file: {}", note.id, e);
+                    continue;
+                }
+            };
+
+            let file = fs::File::create(staging.join(&note.filename))?;
+            std::io::Write::write_all(&mut &file, self.crypto.encrypt_bound(&content, binding)?.as_bytes())?;
+            file.sync_all()?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn stage_rekeyed_notes(
+        &self,
+        notes: &[NoteRecord],
+        crypto: &Crypto,
+        staging: &Path,
+    ) -> Result<RekeyedColumns> {
+        let mut columns = RekeyedColumns::default();
+        if self.private_metadata {
+            for (id, name) in self.tags()? {
+                columns
+                    .tags
+                    .push((id, self.seal_meta(crypto, &name, TAG_BINDING)?));
+            }
+        }
+        if self.private_metadata {
+            for (id, source, link) in self.stored_links(notes)? {
+                columns
+                    .links
+                    .push((id, self.seal_meta(crypto, &link.to_string(), Self::link_binding(&source.uuid))?));
+            }
+        }
+        for revision in self.db.get_revisions(None)? {
+            let note = notes
+                .iter()
+                .find(|note| note.id == revision.note_id)
+                .ok_or(NoterError::NoteNotFound(revision.note_id))?;
+            let content = self
+                .crypto
+                .decrypt_bound(&revision.content, Self::revision_binding(note))?;
+            columns.revisions.push((
+                revision.id,
+                crypto.encrypt_bound(&content, Self::revision_binding(note))?,
+            ));
+        }
+        for note in notes {
+            if self.private_metadata {
+                let title = self.open_title(&note.uuid, &note.title)?;
+                columns
+                    .titles
+                    .push((note.id, self.seal_title(crypto, &note.uuid, &title)?));
+            }
+
+            let decrypted = self.decrypt_note_file(note)?;
+            let reencrypted =
+                crypto.encrypt_bound(&decrypted, Self::binding(&note.uuid, &note.filename))?;
+
+            let file = fs::File::create(staging.join(&note.filename))?;
+            std::io::Write::write_all(&mut &file, reencrypted.as_bytes())?;
+            file.sync_all()?;
+        }
+        Ok(columns)
+    }
+
+    /// Notes matching every criterion of `filter`, newest first.
+    pub fn select_notes(&self, filter: &NoteFilter) -> Result<Vec<NoteRecord>> {
+        for &id in &filter.ids {
+            if self.db.get_note(id)?.is_none() {
+                return Err(NoterError::NoteNotFound(id));
+            }
+        }
+
+        let mut notes = match &filter.tag {
+            Some(tag) => self.list_notes_with_tag(tag)?,
+            None => self.list_notes()?,
+        };
+        if let Some(query) = &filter.query {
+            let hits: Vec<i64> = self
+                .search_content(query)?
+                .into_iter()
+                .map(|hit| hit.note.id)
+                .collect();
+            notes.retain(|note| hits.contains(&note.id));
+        }
+        notes.retain(|note| {
+            let created = note.created_at.date_naive();
+            (filter.ids.is_empty() || filter.ids.contains(&note.id))
+                && filter.since.is_none_or(|since| created >= since)
+                && filter.until.is_none_or(|until| created <= until)
+        });
+        Ok(notes)
+    }
+
+    /// Exports `notes` into `export_dir` (or the configured export
+    /// directory): one file per note for [`ExportFormat::Md`], or a single
+    /// `notes.json` / `notes.html`. Existing files are handled per `policy`;
+    /// renamed note files get the note ID as suffix and single documents
+    /// the export date.
+    pub fn export_notes(
+        &self,
+        notes: &[NoteRecord],
+        export_dir: Option<&Path>,
+        format: ExportFormat,
+        policy: CollisionPolicy,
+    ) -> Result<ExportReport> {
+        let mut report = ExportReport::default();
+        if notes.is_empty() {
+            return Ok(report);
         }
 
         let target_dir = match export_dir {
             Some(dir) => dir.to_path_buf(),
-            None => self.config.export_dir.as_ref()
-                .map(|p| p.clone())
+            None => self.config.export_dir.clone()
                 .unwrap_or_else(|| self.config.notes_dir.join("exports"))
         };
 
         fs::create_dir_all(&target_dir)?;
 
-        let mut success_count = 0;
         let mut errors = Vec::new();
+        let mut exported = Vec::new();
+        let stems = self.export_stems(notes);
 
-        for note in notes {
-            let safe_title = self.sanitize_filename(&note.title);
-            let export_path = target_dir.join(format!("{}.{}", safe_title, self.config.default_extension));
-            
-            match self.export_note(note.id, &export_path) {
-                Ok(_) => {
-                    success_count += 1;
-                    info!("Exported note '{}' to {}", note.title, export_path.display());
+        for (note, stem) in notes.iter().zip(stems) {
+            let result = match format {
+                ExportFormat::Md => {
+                    let path = target_dir.join(format!("{}.{}", stem, self.config.default_extension));
+                    match policy.resolve(path, &note.id.to_string()) {
+                        Some(export_path) => self.export_note(note.id, &export_path).map(|_| {
+                            info!("Exported note '{}' to {}", note.title.as_str(), export_path.display());
+                            true
+                        }),
+                        None => Ok(false),
+                    }
+                }
+                ExportFormat::Json | ExportFormat::Html => {
+                    self.exported_note(note).map(|note| exported.push(note)).map(|_| true)
+                }
+            };
+
+            match result {
+                Ok(true) => report.exported += 1,
+                Ok(false) => {
+                    info!("Skipped note '{}': file already exists", note.title.as_str());
+                    report.skipped += 1;
                 }
                 Err(e) => {
-                    warn!("Failed to export note '{}': {}", note.title, e);
-                    errors.push((note.title, e));
+                    warn!("Failed to export note '{}': {}", note.title.as_str(), e);
+                    report.failed += 1;
+                    errors.push((note.title.clone(), e));
                 }
             }
         }
@@ -164,13 +1496,80 @@ impl NotesManager {
         if !errors.is_empty() {
             let error_msg = errors
                 .iter()
-                .map(|(title, err)| format!("- {}: {}", title, err))
+                .map(|(title, err)| format!("- {}: {}", title.as_str(), err))
                 .collect::<Vec<_>>()
                 .join("\n");
             warn!("Some notes failed to export:\n{}", error_msg);
         }
 
-        Ok((success_count, total_count))
+        let document = match format {
+            ExportFormat::Md => None,
+            ExportFormat::Json => Some(("notes.json", export::to_json(&exported)?)),
+            ExportFormat::Html => Some(("notes.html", export::to_html(&exported))),
+        };
+        if let Some((filename, document)) = document {
+            let document = Zeroizing::new(document);
+            let suffix = Local::now().format("%Y%m%d-%H%M%S").to_string();
+            let Some(export_path) = policy.resolve(target_dir.join(filename), &suffix) else {
+                info!("Skipped {}: file already exists", filename);
+                report.skipped += report.exported;
+                report.exported = 0;
+                return Ok(report);
+            };
+            fs::write(&export_path, document.as_bytes()).map_err(|e| {
+                NoterError::ExportError(format!("Failed to write to {}: {}", export_path.display(), e))
+            })?;
+            info!("Exported {} notes to {}", report.exported, export_path.display());
+        }
+
+        Ok(report)
+    }
+
+    /// Streams `notes` decrypted into a zip or tar archive at `path`,
+    /// followed by a `manifest.json` with each note's metadata and content
+    /// hash.
+    pub fn export_archive(&self, notes: &[NoteRecord], path: &Path) -> Result<ExportReport> {
+        let mut archive = ExportArchive::create(path)?;
+        let mut manifest = Vec::with_capacity(notes.len());
+        let mut report = ExportReport::default();
+
+        for (note, stem) in notes.iter().zip(self.export_stems(notes)) {
+            let name = format!("notes/{}.{}", stem, self.config.default_extension);
+            let result = self.read_note(note.id).and_then(|content| {
+                archive.add(&name, content.as_bytes(), note.updated_at)?;
+                Ok(crypto::sha256_hex(content.as_bytes()))
+            });
+            match result {
+                Ok(sha256) => {
+                    manifest.push(ManifestEntry {
+                        id: note.id,
+                        uuid: note.uuid.clone(),
+                        title: note.title.clone(),
+                        created_at: note.created_at.to_rfc3339(),
+                        updated_at: note.updated_at.to_rfc3339(),
+                        path: name,
+                        sha256,
+                    });
+                    report.exported += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to export note '{}': {}", note.title.as_str(), e);
+                    report.failed += 1;
+                }
+            }
+        }
+
+        archive.finish(&manifest)?;
+        info!("Exported {} notes to {}", report.exported, path.display());
+        Ok(report)
+    }
+
+    fn export_stems(&self, notes: &[NoteRecord]) -> Vec<String> {
+        let titles: Vec<(i64, String)> = notes
+            .iter()
+            .map(|note| (note.id, self.sanitize_filename(&note.title)))
+            .collect();
+        export::unique_stems(&titles)
     }
 
     fn export_note(&self, id: i64, export_path: &Path) -> Result<()> {
@@ -178,13 +1577,31 @@ impl NotesManager {
             NoterError::ExportError(format!("Failed to read note {}: {}", id, e))
         })?;
 
-        fs::write(export_path, content).map_err(|e| {
+        fs::write(export_path, content.as_bytes()).map_err(|e| {
             NoterError::ExportError(format!("Failed to write to {}: {}", export_path.display(), e))
         })?;
 
         Ok(())
     }
 
+    /// `note` must come from [`Self::list_notes`] so its title is readable.
+    fn exported_note(&self, note: &NoteRecord) -> Result<ExportedNote> {
+        let content = crypto::into_string(self.decrypt_note_file(note)?).map_err(|e| {
+            NoterError::ExportError(format!("Failed to read note {}: {}", note.id, e))
+        })?;
+        let (front, body) = frontmatter::split(&content);
+        let tags = front.map(NoteMetadata::parse).unwrap_or_default().tags;
+        Ok(ExportedNote {
+            id: note.id,
+            uuid: note.uuid.clone(),
+            title: note.title.clone(),
+            created_at: note.created_at.to_rfc3339(),
+            updated_at: note.updated_at.to_rfc3339(),
+            tags,
+            body: Zeroizing::new(body.to_string()),
+        })
+    }
+
     fn sanitize_filename(&self, filename: &str) -> String {
         let safe_chars = filename
             .chars()
@@ -199,9 +1616,35 @@ impl NotesManager {
             .collect()
     }
 
-    fn format_filename(&self, title: &str) -> String {
+    fn format_filename(&self, title: &str, created: DateTime<Local>) -> String {
+        if self.private_metadata {
+            return self.opaque_filename();
+        }
+
         let safe_title = title.replace(|c: char| !c.is_alphanumeric() && c != '-', "-");
-        let timestamp = Local::now().format("%Y%m%d-%H%M%S");
+        let timestamp = created.format("%Y%m%d-%H%M%S");
         format!("{}-{}.{}", timestamp, safe_title, self.config.default_extension)
     }
+
+    /// Like [`Self::format_filename`], with a numeric suffix when notes with
+    /// the same title and creation time already exist.
+    fn unique_filename(&self, title: &str, created: DateTime<Local>) -> String {
+        let filename = self.format_filename(title, created);
+        let path = Path::new(&filename);
+        let (stem, ext) = (
+            path.file_stem().unwrap_or_default().to_string_lossy(),
+            path.extension().unwrap_or_default().to_string_lossy(),
+        );
+        let mut candidate = filename.clone();
+        let mut n = 2;
+        while self.notes_dir.join(&candidate).exists() {
+            candidate = format!("{}-{}.{}", stem, n, ext);
+            n += 1;
+        }
+        candidate
+    }
+
+    fn opaque_filename(&self) -> String {
+        format!("{}.{}", Uuid::new_v4().simple(), self.config.default_extension)
+    }
 }
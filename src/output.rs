@@ -0,0 +1,180 @@
+//! How command results are printed: colored text for people, or plain text
+//! and JSON for scripts.
+
+use crate::db::NoteRecord;
+use crate::note::NoteLink;
+use crate::error::{NoterError, Result};
+use colored::Colorize;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Colored, human-readable output.
+    #[default]
+    Table,
+    /// The same text without colors; note lists are tab-separated.
+    Plain,
+    /// One JSON document on stdout; errors as JSON on stderr.
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown output format '{}' (expected json, table or plain)", other)),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Table => "table",
+            Self::Plain => "plain",
+            Self::Json => "json",
+        })
+    }
+}
+
+/// Prints command results in the selected [`OutputFormat`]. Human-readable
+/// messages are dropped in JSON mode, where the command's data is printed
+/// instead.
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    format: OutputFormat,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Self {
+        if format != OutputFormat::Table {
+            colored::control::set_override(false);
+        }
+        Self { format }
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    /// Prints `value` as pretty JSON on stdout. A closed pipe (e.g. output
+    /// piped into `head`) is not an error.
+    pub fn json<T: Serialize + ?Sized>(&self, value: &T) -> Result<()> {
+        let json = serde_json::to_string_pretty(value)
+            .map_err(|e| NoterError::InvalidInput(e.to_string()))?;
+        match writeln!(io::stdout().lock(), "{}", json) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Prints `text` exactly as given, with the same handling of a closed
+    /// pipe as [`Output::json`].
+    pub fn raw(&self, text: &str) -> Result<()> {
+        match io::stdout().lock().write_all(text.as_bytes()) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn success(&self, message: &str) {
+        if !self.is_json() {
+            println!("{}", message.green());
+        }
+    }
+
+    pub fn notice(&self, message: &str) {
+        if !self.is_json() {
+            println!("{}", message.yellow());
+        }
+    }
+
+    /// Warnings go to stderr so they never mix with JSON on stdout.
+    pub fn warning(&self, message: &str) {
+        eprintln!("{}", message.yellow());
+    }
+
+    pub fn error(&self, error: &NoterError) {
+        if self.is_json() {
+            let value = serde_json::json!({
+                "error": {
+                    "code": error.code(),
+                    "message": error.to_string(),
+                }
+            });
+            eprintln!("{}", value);
+            return;
+        }
+        eprintln!("{} {}", "Error:".red(), error);
+        if let NoterError::EditorNotFound = error {
+            eprintln!("{}", "Set the $EDITOR environment variable or 'editor' in config.toml.".yellow());
+        }
+    }
+
+    /// Prints a list of notes, or `empty` if there are none.
+    pub fn notes(&self, notes: &[NoteRecord], empty: &str) -> Result<()> {
+        match self.format {
+            OutputFormat::Json => return self.json(notes),
+            _ if notes.is_empty() => self.notice(empty),
+            OutputFormat::Plain => {
+                for note in notes {
+                    println!("{}\t{}\t{}", note.id, note.title.as_str(), note.filename);
+                }
+            }
+            OutputFormat::Table => {
+                for note in notes {
+                    println!("{} {} {}",
+                        format!("[{}]", note.id).cyan(),
+                        note.title.bright_white(),
+                        format!("({})", note.filename).dimmed()
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints wiki links and the notes they resolve to, or `empty` if there
+    /// are none. `sources` also names the note each link is in.
+    pub fn links(&self, links: &[NoteLink], sources: bool, empty: &str) -> Result<()> {
+        match self.format {
+            OutputFormat::Json => return self.json(links),
+            _ if links.is_empty() => self.notice(empty),
+            OutputFormat::Plain => {
+                for link in links {
+                    let (id, title) = match &link.note {
+                        Some(note) => (note.id.to_string(), note.title.as_str()),
+                        None => (String::new(), ""),
+                    };
+                    println!("{}\t{}\t{}\t{}", link.source.id, link.target, id, title);
+                }
+            }
+            OutputFormat::Table => {
+                for link in links {
+                    let source = if sources {
+                        format!("{} {} ", format!("[{}]", link.source.id).cyan(), link.source.title.bright_white())
+                    } else {
+                        String::new()
+                    };
+                    let target = match &link.note {
+                        Some(note) => format!("{} {}", format!("[{}]", note.id).cyan(), note.title.bright_white()),
+                        None => "broken".red().to_string(),
+                    };
+                    println!("{}{} → {}", source, format!("[[{}]]", link.target).dimmed(), target);
+                }
+            }
+        }
+        Ok(())
+    }
+}
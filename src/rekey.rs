@@ -0,0 +1,116 @@
+//! Crash-safe bookkeeping for `noters rekey`.
+//!
+//! Re-encrypted notes are staged in `<notes_dir>/.rekey` next to a journal
+//! holding the fingerprint of the new key. The database write that records
+//! the new key fingerprint is the commit point: if the database already
+//! carries the journal's fingerprint the rotation is rolled forward,
+//! otherwise the staged files are discarded and the old key stays valid.
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::error::{NoterError, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
+
+pub const STAGING_DIR: &str = ".rekey";
+const JOURNAL_FILE: &str = "journal.toml";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RekeyJournal {
+    pub key_check: String,
+    pub use_passphrase: bool,
+    /// New legacy key, persisted here so it survives a crash between the
+    /// database commit and the config update.
+    pub encryption_key: Option<String>,
+}
+
+impl Drop for RekeyJournal {
+    fn drop(&mut self) {
+        self.encryption_key.zeroize();
+    }
+}
+
+pub fn staging_dir(notes_dir: &Path) -> PathBuf {
+    notes_dir.join(STAGING_DIR)
+}
+
+pub fn write_journal(staging: &Path, journal: &RekeyJournal) -> Result<()> {
+    let contents = Zeroizing::new(
+        toml::to_string_pretty(journal).map_err(|e| NoterError::Config(e.to_string()))?,
+    );
+    let path = staging.join(JOURNAL_FILE);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path)?;
+    std::io::Write::write_all(&mut file, contents.as_bytes())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+fn read_journal(staging: &Path) -> Result<Option<RekeyJournal>> {
+    let path = staging.join(JOURNAL_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = Zeroizing::new(fs::read_to_string(path)?);
+    toml::from_str(&contents)
+        .map(Some)
+        .map_err(|e| NoterError::Config(format!("Corrupt rekey journal: {}", e)))
+}
+
+/// Moves staged notes over the originals and persists the new config.
+/// Safe to call repeatedly: already-moved files are simply gone from staging.
+pub fn complete(staging: &Path, notes_dir: &Path, config: &mut Config) -> Result<()> {
+    let mut journal = read_journal(staging)?
+        .ok_or_else(|| NoterError::Config("Missing rekey journal".to_string()))?;
+
+    for entry in fs::read_dir(staging)? {
+        let entry = entry?;
+        if entry.file_name() == JOURNAL_FILE {
+            continue;
+        }
+        fs::rename(entry.path(), notes_dir.join(entry.file_name()))?;
+    }
+
+    config.use_passphrase = journal.use_passphrase;
+    if let Some(key) = journal.encryption_key.take() {
+        config.encryption_key = Zeroizing::new(key);
+    }
+    config.save()?;
+
+    fs::remove_dir_all(staging)?;
+    Ok(())
+}
+
+/// Finishes or rolls back a rotation interrupted by a crash.
+pub fn recover(config: &mut Config, db: &Database) -> Result<()> {
+    let notes_dir = config.notes_dir.clone();
+    let staging = staging_dir(&notes_dir);
+    if !staging.exists() {
+        return Ok(());
+    }
+
+    let committed = match read_journal(&staging)? {
+        Some(journal) => db.get_meta("key_check")?.as_deref() == Some(&journal.key_check),
+        None => false,
+    };
+
+    if committed {
+        warn!("Completing interrupted key rotation");
+        complete(&staging, &notes_dir, config)?;
+    } else {
+        warn!("Discarding interrupted key rotation");
+        fs::remove_dir_all(&staging)?;
+    }
+    info!("Recovered from interrupted key rotation");
+    Ok(())
+}
@@ -0,0 +1,311 @@
+//! Rendering a note's Markdown for the terminal with ANSI colors.
+
+use crate::db::NoteRecord;
+use crate::frontmatter::NoteMetadata;
+use colored::{ColoredString, Colorize};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+/// Renders a note with a short header (title, dates and tags) in place of
+/// its front matter.
+pub fn note(note: &NoteRecord, content: &str) -> String {
+    let metadata = NoteMetadata::from_content(content);
+    let mut out = format!("{}\n", note.title.bright_white().bold().underline());
+    let mut meta = format!(
+        "Created {} · Updated {}",
+        note.created_at.format("%Y-%m-%d %H:%M"),
+        note.updated_at.format("%Y-%m-%d %H:%M")
+    );
+    if !metadata.tags.is_empty() {
+        meta.push_str(&format!(" · #{}", metadata.tags.join(" #")));
+    }
+    out.push_str(&format!("{}\n\n", meta.dimmed()));
+    out.push_str(&markdown(crate::frontmatter::body(content)));
+    out
+}
+
+/// Renders Markdown as styled text. Block structure is kept (headings,
+/// lists, quotes and code blocks) and inline markup becomes colors.
+pub fn markdown(text: &str) -> String {
+    let mut renderer = Renderer::default();
+    for event in Parser::new_ext(text, Options::all()) {
+        renderer.event(event);
+    }
+    let mut out = renderer.out.trim_end().to_string();
+    out.push('\n');
+    out
+}
+
+#[derive(Clone, Copy)]
+enum Style {
+    Heading(HeadingLevel),
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Link,
+    Code,
+}
+
+#[derive(Default)]
+struct Renderer {
+    out: String,
+    styles: Vec<Style>,
+    /// Line prefixes of the enclosing quotes and list items.
+    prefixes: Vec<String>,
+    /// The next number of each enclosing list, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    links: Vec<String>,
+    /// Cells of the table row being read.
+    row: Vec<String>,
+    cell: Option<String>,
+    line_start: bool,
+    /// Set right after a list marker, so the item's first block stays on
+    /// the marker's line.
+    item_start: bool,
+}
+
+impl Renderer {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(&text),
+            Event::Code(code) => {
+                self.styles.push(Style::Code);
+                self.text(&code);
+                self.styles.pop();
+            }
+            Event::InlineMath(math) | Event::DisplayMath(math) => {
+                self.styles.push(Style::Code);
+                self.text(&math);
+                self.styles.pop();
+            }
+            Event::Html(html) | Event::InlineHtml(html) => {
+                let html = html.to_string();
+                self.write(&html, |s| s.dimmed());
+            }
+            Event::FootnoteReference(label) => {
+                let label = format!("[^{}]", label);
+                self.write(&label, |s| s.dimmed());
+            }
+            Event::SoftBreak | Event::HardBreak => self.newline(),
+            Event::Rule => {
+                self.block_gap();
+                self.write(&"─".repeat(40), |s| s.dimmed());
+                self.newline();
+                self.blank_line();
+            }
+            Event::TaskListMarker(done) => {
+                let marker = if done { "[x] " } else { "[ ] " };
+                self.write(marker, |s| if done { s.green() } else { s.yellow() });
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.block_gap(),
+            Tag::Heading { level, .. } => {
+                self.block_gap();
+                self.styles.push(Style::Heading(level));
+                let marker = format!("{} ", "#".repeat(level as usize));
+                self.text(&marker);
+            }
+            Tag::BlockQuote(_) => {
+                self.block_gap();
+                self.prefixes.push(format!("{} ", "│".dimmed()));
+            }
+            Tag::CodeBlock(kind) => {
+                self.block_gap();
+                if let CodeBlockKind::Fenced(lang) = kind {
+                    if !lang.is_empty() {
+                        let lang = lang.to_string();
+                        self.write(&lang, |s| s.dimmed().italic());
+                        self.newline();
+                    }
+                }
+                self.prefixes.push("    ".to_string());
+                self.styles.push(Style::Code);
+            }
+            Tag::List(start) => {
+                if self.lists.is_empty() {
+                    self.block_gap();
+                } else if !self.line_start {
+                    self.newline();
+                }
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                if !self.line_start {
+                    self.newline();
+                }
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.write(&marker, |s| s.cyan());
+                self.item_start = true;
+                self.prefixes.push(" ".repeat(marker.chars().count()));
+            }
+            Tag::Emphasis => self.styles.push(Style::Emphasis),
+            Tag::Strong => self.styles.push(Style::Strong),
+            Tag::Strikethrough => self.styles.push(Style::Strikethrough),
+            Tag::Link { dest_url, .. } => {
+                self.styles.push(Style::Link);
+                self.links.push(dest_url.to_string());
+            }
+            Tag::Image { dest_url, .. } => {
+                self.write("[image: ", |s| s.dimmed());
+                self.links.push(dest_url.to_string());
+            }
+            Tag::Table(_) => self.block_gap(),
+            Tag::TableCell => self.cell = Some(String::new()),
+            Tag::FootnoteDefinition(label) => {
+                self.block_gap();
+                let label = format!("[^{}]: ", label);
+                self.write(&label, |s| s.dimmed());
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => self.newline(),
+            TagEnd::Heading(_) => {
+                self.styles.pop();
+                self.newline();
+            }
+            TagEnd::BlockQuote(_) => {
+                self.prefixes.pop();
+                self.blank_line();
+            }
+            TagEnd::CodeBlock => {
+                self.styles.pop();
+                self.prefixes.pop();
+                if !self.line_start {
+                    self.newline();
+                }
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                if !self.line_start {
+                    self.newline();
+                }
+            }
+            TagEnd::Item => {
+                self.prefixes.pop();
+                if !self.line_start {
+                    self.newline();
+                }
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => {
+                self.styles.pop();
+            }
+            TagEnd::Link => {
+                self.styles.pop();
+                if let Some(url) = self.links.pop() {
+                    let url = format!(" <{}>", url);
+                    self.write(&url, |s| s.dimmed());
+                }
+            }
+            TagEnd::Image => {
+                let url = self.links.pop().unwrap_or_default();
+                let url = format!(" <{}>]", url);
+                self.write(&url, |s| s.dimmed());
+            }
+            TagEnd::TableCell => {
+                if let Some(cell) = self.cell.take() {
+                    self.row.push(cell);
+                }
+            }
+            TagEnd::TableHead | TagEnd::TableRow => {
+                let row = std::mem::take(&mut self.row).join(" │ ");
+                if tag == TagEnd::TableHead {
+                    self.write(&row, |s| s.bold());
+                } else {
+                    self.write(&row, |s| s.normal());
+                }
+                self.newline();
+            }
+            TagEnd::Table | TagEnd::FootnoteDefinition => self.newline(),
+            _ => {}
+        }
+    }
+
+    /// Writes text in the current inline style; table cells are collected
+    /// unstyled so their columns can be joined.
+    fn text(&mut self, text: &str) {
+        if let Some(cell) = &mut self.cell {
+            cell.push_str(text);
+            return;
+        }
+        let styles = self.styles.clone();
+        self.write(text, |s| apply(s, &styles));
+    }
+
+    fn write(&mut self, text: &str, style: impl Fn(&str) -> ColoredString) {
+        self.item_start = false;
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.newline();
+            }
+            if line.is_empty() {
+                continue;
+            }
+            if self.line_start {
+                self.out.push_str(&self.prefixes.concat());
+                self.line_start = false;
+            }
+            self.out.push_str(&style(line).to_string());
+        }
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.line_start = true;
+    }
+
+    /// Ends the current line with an empty one, unless the output already
+    /// ends with one.
+    fn blank_line(&mut self) {
+        if !self.line_start {
+            self.newline();
+        }
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.newline();
+        }
+    }
+
+    /// Separates a new block from the one before it.
+    fn block_gap(&mut self) {
+        if self.out.is_empty() || self.item_start {
+            return;
+        }
+        if !self.line_start {
+            self.newline();
+        }
+        if !self.out.ends_with("\n\n") {
+            self.newline();
+        }
+    }
+}
+
+fn apply(text: &str, styles: &[Style]) -> ColoredString {
+    let mut styled = ColoredString::from(text);
+    for style in styles {
+        styled = match style {
+            Style::Heading(HeadingLevel::H1) => styled.bright_magenta().bold().underline(),
+            Style::Heading(HeadingLevel::H2) => styled.bright_cyan().bold(),
+            Style::Heading(_) => styled.bright_blue().bold(),
+            Style::Emphasis => styled.italic(),
+            Style::Strong => styled.bold(),
+            Style::Strikethrough => styled.strikethrough(),
+            Style::Link => styled.bright_blue().underline(),
+            Style::Code => styled.yellow(),
+        };
+    }
+    styled
+}
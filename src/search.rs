@@ -0,0 +1,640 @@
+//! Full-text search over decrypted notes.
+//!
+//! Nothing is persisted: the index is built in memory from decrypted note
+//! bodies for each query, so no plaintext ever reaches the disk.
+//!
+//! Query syntax: bare words must all match, `"exact phrase"`, `prefix*`,
+//! `AND`, `OR`, `NOT` (or a leading `-`), and parentheses for grouping.
+//! `title:word` (or `title:"a phrase"`) only matches titles, and `tag:name`
+//! matches notes with that tag in their front matter.
+
+use crate::db::NoteRecord;
+use crate::error::{NoterError, Result};
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+const TITLE_WEIGHT: f64 = 3.0;
+const SNIPPET_RADIUS: usize = 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+    /// A term, prefix or phrase that must occur in the title.
+    Title(Box<Query>),
+    /// A front matter tag, lowercased.
+    Tag(String),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    Title(String, bool),
+    Tag(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = lex(input)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let query = parser
+            .parse_or()?
+            .ok_or_else(|| NoterError::InvalidInput("Empty search query".to_string()))?;
+        if parser.pos < parser.tokens.len() {
+            return Err(NoterError::InvalidInput(
+                "Unexpected ')' in search query".to_string(),
+            ));
+        }
+        Ok(query)
+    }
+
+    fn matches(&self, doc: &Document) -> bool {
+        match self {
+            Query::Term(term) => doc.tokens().any(|t| *t.text == *term),
+            Query::Prefix(prefix) => doc.tokens().any(|t| t.text.starts_with(prefix.as_str())),
+            Query::Phrase(words) => doc.has_phrase(words),
+            Query::Title(q) => !q.leaf_ranges(&doc.title_tokens).is_empty(),
+            Query::Tag(tag) => doc.tags.contains(tag),
+            Query::And(a, b) => a.matches(doc) && b.matches(doc),
+            Query::Or(a, b) => a.matches(doc) || b.matches(doc),
+            Query::Not(q) => !q.matches(doc),
+        }
+    }
+
+    /// Leaf queries that contribute to ranking and highlighting. Anything
+    /// under a `NOT` is excluded, and tags only filter.
+    fn positive_leaves<'a>(&'a self, leaves: &mut Vec<&'a Query>) {
+        match self {
+            Query::And(a, b) | Query::Or(a, b) => {
+                a.positive_leaves(leaves);
+                b.positive_leaves(leaves);
+            }
+            Query::Not(_) | Query::Tag(_) => {}
+            leaf => leaves.push(leaf),
+        }
+    }
+
+    /// Byte ranges of the title matched by this leaf.
+    fn title_ranges(&self, doc: &Document) -> Vec<(usize, usize)> {
+        match self {
+            Query::Title(q) => q.leaf_ranges(&doc.title_tokens),
+            leaf => leaf.leaf_ranges(&doc.title_tokens),
+        }
+    }
+
+    /// Byte ranges in `tokens` matched by this leaf. Title-only leaves
+    /// match nothing here; see [`Query::title_ranges`].
+    fn leaf_ranges(&self, tokens: &[DocToken]) -> Vec<(usize, usize)> {
+        match self {
+            Query::Term(term) => tokens
+                .iter()
+                .filter(|t| *t.text == *term)
+                .map(|t| (t.start, t.end))
+                .collect(),
+            Query::Prefix(prefix) => tokens
+                .iter()
+                .filter(|t| t.text.starts_with(prefix.as_str()))
+                .map(|t| (t.start, t.end))
+                .collect(),
+            Query::Phrase(words) if !words.is_empty() => tokens
+                .windows(words.len())
+                .filter(|w| w.iter().zip(words).all(|(t, word)| *t.text == *word))
+                .map(|w| (w[0].start, w[w.len() - 1].end))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn lex(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Phrase(read_phrase(&mut chars)?));
+            }
+            '-' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let qualified = word
+                    .split_once(':')
+                    .map(|(field, value)| (field.to_lowercase(), value.to_string()));
+                tokens.push(match (word.as_str(), qualified) {
+                    ("AND", _) => Token::And,
+                    ("OR", _) => Token::Or,
+                    ("NOT", _) => Token::Not,
+                    (_, Some((field, value))) if field == "title" || field == "tag" => {
+                        let quoted = value.is_empty() && chars.peek() == Some(&'"');
+                        let value = if quoted {
+                            chars.next();
+                            read_phrase(&mut chars)?
+                        } else {
+                            value
+                        };
+                        if field == "title" {
+                            Token::Title(value, quoted)
+                        } else {
+                            Token::Tag(value)
+                        }
+                    }
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Reads up to the closing quote of a phrase whose opening quote has been
+/// consumed.
+fn read_phrase(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String> {
+    let mut phrase = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(phrase),
+            Some(c) => phrase.push(c),
+            None => {
+                return Err(NoterError::InvalidInput(
+                    "Unterminated phrase in search query".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+/// The query for an unquoted word: a term, a `prefix*`, or a phrase if
+/// punctuation splits it into several words. `None` if nothing is left.
+fn word_query(word: &str) -> Option<Query> {
+    let is_prefix = word.ends_with('*');
+    let words = normalize(word.trim_end_matches('*'));
+    match words.len() {
+        0 => None,
+        1 if is_prefix => Some(Query::Prefix(words.into_iter().next().unwrap())),
+        1 => Some(Query::Term(words.into_iter().next().unwrap())),
+        _ => Some(Query::Phrase(words)),
+    }
+}
+
+fn phrase_query(phrase: &str) -> Option<Query> {
+    let words = normalize(phrase);
+    (!words.is_empty()).then_some(Query::Phrase(words))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<Option<Query>> {
+        let mut left = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let right = self.parse_and()?;
+            left = match (left, right) {
+                (Some(l), Some(r)) => Some(Query::Or(Box::new(l), Box::new(r))),
+                _ => {
+                    return Err(NoterError::InvalidInput(
+                        "OR needs a term on both sides".to_string(),
+                    ))
+                }
+            };
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Option<Query>> {
+        let mut left: Option<Query> = None;
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(Token::And) => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let Some(right) = self.parse_unary()? else {
+                continue;
+            };
+            left = Some(match left {
+                Some(l) => Query::And(Box::new(l), Box::new(right)),
+                None => right,
+            });
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Option<Query>> {
+        let token = self.tokens[self.pos].clone();
+        self.pos += 1;
+        match token {
+            Token::Not => {
+                if matches!(self.peek(), None | Some(Token::Close) | Some(Token::Or)) {
+                    return Err(NoterError::InvalidInput(
+                        "NOT needs a term to negate".to_string(),
+                    ));
+                }
+                Ok(self.parse_unary()?.map(|q| Query::Not(Box::new(q))))
+            }
+            Token::Open => {
+                let inner = self.parse_or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(NoterError::InvalidInput(
+                        "Missing ')' in search query".to_string(),
+                    ));
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Token::Word(word) => Ok(word_query(&word)),
+            Token::Phrase(phrase) => Ok(phrase_query(&phrase)),
+            Token::Title(value, quoted) => {
+                let query = if quoted { phrase_query(&value) } else { word_query(&value) };
+                query
+                    .map(|q| Some(Query::Title(Box::new(q))))
+                    .ok_or_else(|| NoterError::InvalidInput("title: needs a word to match".to_string()))
+            }
+            Token::Tag(tag) => {
+                let tag = tag.trim();
+                if tag.is_empty() {
+                    return Err(NoterError::InvalidInput("tag: needs a tag name".to_string()));
+                }
+                Ok(Some(Query::Tag(tag.to_lowercase())))
+            }
+            Token::And | Token::Or | Token::Close => Err(NoterError::InvalidInput(
+                "Misplaced operator in search query".to_string(),
+            )),
+        }
+    }
+}
+
+fn normalize(text: &str) -> Vec<String> {
+    tokenize(text).into_iter().map(|t| t.text.to_string()).collect()
+}
+
+#[derive(Debug, Clone)]
+struct DocToken {
+    /// Lowercased word, wiped on drop like the body it came from.
+    text: Zeroizing<String>,
+    start: usize,
+    end: usize,
+}
+
+fn tokenize(text: &str) -> Vec<DocToken> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push(DocToken {
+                    text: Zeroizing::new(text[s..i].to_lowercase()),
+                    start: s,
+                    end: i,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        tokens.push(DocToken {
+            text: Zeroizing::new(text[s..].to_lowercase()),
+            start: s,
+            end: text.len(),
+        });
+    }
+    tokens
+}
+
+struct Document {
+    note: NoteRecord,
+    title_tokens: Vec<DocToken>,
+    /// Front matter tags, lowercased.
+    tags: Vec<String>,
+    body: Zeroizing<String>,
+    body_tokens: Vec<DocToken>,
+}
+
+impl Document {
+    fn tokens(&self) -> impl Iterator<Item = &DocToken> {
+        self.title_tokens.iter().chain(&self.body_tokens)
+    }
+
+    fn has_phrase(&self, words: &[String]) -> bool {
+        [&self.title_tokens, &self.body_tokens].into_iter().any(|tokens| {
+            tokens
+                .windows(words.len())
+                .any(|w| w.iter().zip(words).all(|(t, word)| *t.text == *word))
+        })
+    }
+}
+
+/// A short excerpt of a note body with the byte ranges that matched.
+#[derive(Debug, Clone, Serialize)]
+pub struct Snippet {
+    pub text: Zeroizing<String>,
+    pub highlights: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub note: NoteRecord,
+    pub score: f64,
+    pub snippet: Option<Snippet>,
+}
+
+#[derive(Default)]
+pub struct SearchIndex {
+    docs: Vec<Document>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a note; `body` is the decrypted content without front matter.
+    pub fn add(&mut self, note: NoteRecord, tags: &[String], body: Zeroizing<String>) {
+        let title_tokens = tokenize(&note.title);
+        let body_tokens = tokenize(&body);
+        self.docs.push(Document {
+            note,
+            title_tokens,
+            tags: tags.iter().map(|tag| tag.to_lowercase()).collect(),
+            body,
+            body_tokens,
+        });
+    }
+
+    /// Returns matching notes, best first.
+    pub fn search(self, query: &Query) -> Vec<SearchHit> {
+        let mut leaves = Vec::new();
+        query.positive_leaves(&mut leaves);
+
+        let total = self.docs.len() as f64;
+        let avg_len = self
+            .docs
+            .iter()
+            .map(|d| d.body_tokens.len() as f64)
+            .sum::<f64>()
+            / total.max(1.0);
+        let idf: Vec<f64> = leaves
+            .iter()
+            .map(|leaf| {
+                let df = self.docs.iter().filter(|d| leaf.matches(d)).count() as f64;
+                ((total + 1.0) / (df + 0.5)).ln()
+            })
+            .collect();
+
+        let mut hits: Vec<SearchHit> = self
+            .docs
+            .into_iter()
+            .filter(|doc| query.matches(doc))
+            .map(|doc| {
+                let length_norm = 0.25 + 0.75 * doc.body_tokens.len() as f64 / avg_len.max(1.0);
+                let score = leaves
+                    .iter()
+                    .zip(&idf)
+                    .map(|(leaf, idf)| {
+                        let title_tf = leaf.title_ranges(&doc).len() as f64;
+                        let body_tf = leaf.leaf_ranges(&doc.body_tokens).len() as f64;
+                        let tf = TITLE_WEIGHT * title_tf + body_tf;
+                        idf * tf * 2.2 / (tf + 1.2 * length_norm)
+                    })
+                    .fold(0.0, |sum, score| sum + score);
+                let snippet = make_snippet(&doc, &leaves);
+                SearchHit {
+                    note: doc.note,
+                    score,
+                    snippet,
+                }
+            })
+            .collect();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.note.created_at.cmp(&a.note.created_at))
+        });
+        hits
+    }
+}
+
+fn make_snippet(doc: &Document, leaves: &[&Query]) -> Option<Snippet> {
+    let mut ranges: Vec<(usize, usize)> = leaves
+        .iter()
+        .flat_map(|leaf| leaf.leaf_ranges(&doc.body_tokens))
+        .collect();
+    ranges.sort_unstable();
+    let &(first_start, first_end) = ranges.first()?;
+
+    let mut start = first_start.saturating_sub(SNIPPET_RADIUS);
+    while !doc.body.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (first_end + SNIPPET_RADIUS).min(doc.body.len());
+    while !doc.body.is_char_boundary(end) {
+        end += 1;
+    }
+
+    // Avoid cutting words in half at either edge.
+    if start > 0 {
+        if let Some(space) = doc.body[start..first_start].find(char::is_whitespace) {
+            start = first_start - doc.body[start + space..first_start].trim_start().len();
+        }
+    }
+    if end < doc.body.len() {
+        if let Some(space) = doc.body[first_end..end].rfind(char::is_whitespace) {
+            end = first_end + space;
+        }
+    }
+
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if end < doc.body.len() { "…" } else { "" };
+    let excerpt = &doc.body[start..end];
+    let mut text = Zeroizing::new(String::with_capacity(prefix.len() + excerpt.len() + suffix.len()));
+    text.push_str(prefix);
+    text.extend(excerpt.chars().map(|c| if matches!(c, '\n' | '\r' | '\t') { ' ' } else { c }));
+    text.push_str(suffix);
+
+    let mut highlights: Vec<(usize, usize)> = Vec::new();
+    for (s, e) in ranges.into_iter().filter(|&(s, e)| s >= start && e <= end) {
+        let (s, e) = (s - start + prefix.len(), e - start + prefix.len());
+        match highlights.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => highlights.push((s, e)),
+        }
+    }
+
+    Some(Snippet { text, highlights })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+
+    fn term(word: &str) -> Query {
+        Query::Term(word.to_string())
+    }
+
+    fn and(a: Query, b: Query) -> Query {
+        Query::And(Box::new(a), Box::new(b))
+    }
+
+    fn not(q: Query) -> Query {
+        Query::Not(Box::new(q))
+    }
+
+    fn phrase(words: &[&str]) -> Query {
+        Query::Phrase(words.iter().map(|w| w.to_string()).collect())
+    }
+
+    fn parse(input: &str) -> Query {
+        Query::parse(input).unwrap()
+    }
+
+    #[test]
+    fn bare_words_must_all_match() {
+        assert_eq!(parse("Rust notes"), and(term("rust"), term("notes")));
+        assert_eq!(parse("a AND b"), and(term("a"), term("b")));
+        assert_eq!(
+            parse("a OR b c"),
+            Query::Or(Box::new(term("a")), Box::new(and(term("b"), term("c"))))
+        );
+        assert_eq!(parse("(a OR b) c"), and(Query::Or(Box::new(term("a")), Box::new(term("b"))), term("c")));
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(parse("\"Exact  Phrase\""), phrase(&["exact", "phrase"]));
+        assert_eq!(parse("\"one\""), phrase(&["one"]));
+        assert_eq!(parse("x \"AND\""), and(term("x"), phrase(&["and"])));
+        assert!(Query::parse("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn prefixes_and_punctuation() {
+        assert_eq!(parse("enc*"), Query::Prefix("enc".to_string()));
+        assert_eq!(parse("e-mail"), phrase(&["e", "mail"]));
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(parse("a -b"), and(term("a"), not(term("b"))));
+        assert_eq!(parse("a NOT b"), and(term("a"), not(term("b"))));
+        assert_eq!(parse("-\"x y\""), not(phrase(&["x", "y"])));
+        assert!(Query::parse("a NOT").is_err());
+        assert!(Query::parse("NOT OR b").is_err());
+    }
+
+    #[test]
+    fn qualifiers() {
+        assert_eq!(parse("title:Plan"), Query::Title(Box::new(term("plan"))));
+        assert_eq!(parse("TITLE:plan*"), Query::Title(Box::new(Query::Prefix("plan".to_string()))));
+        assert_eq!(
+            parse("title:\"road map\""),
+            Query::Title(Box::new(phrase(&["road", "map"])))
+        );
+        assert_eq!(parse("tag:Work x"), and(Query::Tag("work".to_string()), term("x")));
+        assert_eq!(parse("-tag:draft"), not(Query::Tag("draft".to_string())));
+        // Anything else before a colon is just text.
+        assert_eq!(parse("note:x"), phrase(&["note", "x"]));
+        assert!(Query::parse("tag:").is_err());
+        assert!(Query::parse("title:").is_err());
+        assert!(Query::parse("title:\"open").is_err());
+    }
+
+    #[test]
+    fn empty_queries() {
+        for input in ["", "   ", "()", "\"\"", "-*"] {
+            assert!(Query::parse(input).is_err(), "{:?} should be rejected", input);
+        }
+        assert!(Query::parse("a OR").is_err());
+        assert!(Query::parse("a )").is_err());
+        assert!(Query::parse("(a").is_err());
+    }
+
+    fn note(id: i64, title: &str) -> NoteRecord {
+        NoteRecord {
+            id,
+            uuid: format!("uuid-{}", id),
+            title: Zeroizing::new(title.to_string()),
+            filename: format!("{}.md", id),
+            created_at: Local::now(),
+            updated_at: Local::now(),
+        }
+    }
+
+    fn search(query: &str) -> Vec<i64> {
+        let mut index = SearchIndex::new();
+        index.add(note(1, "Project plan"), &["Work".to_string()], Zeroizing::new("Budget and timeline".to_string()));
+        index.add(note(2, "Groceries"), &[], Zeroizing::new("Milk, eggs and a plan for dinner".to_string()));
+        index.add(note(3, "Holiday"), &["travel".to_string()], Zeroizing::new("Plan the route".to_string()));
+        index.search(&parse(query)).into_iter().map(|hit| hit.note.id).collect()
+    }
+
+    #[test]
+    fn qualifiers_filter_notes() {
+        assert_eq!(search("title:plan"), [1]);
+        let mut all = search("plan");
+        all.sort();
+        assert_eq!(all, [1, 2, 3]);
+        assert_eq!(search("tag:work"), [1]);
+        let mut untagged_work = search("plan -tag:work");
+        untagged_work.sort();
+        assert_eq!(untagged_work, [2, 3]);
+        assert_eq!(search("\"plan for\""), [2]);
+    }
+
+    #[test]
+    fn title_matches_are_not_highlighted_in_the_body() {
+        let mut index = SearchIndex::new();
+        index.add(note(1, "Plan"), &[], Zeroizing::new("The plan is simple".to_string()));
+        let hits = index.search(&parse("title:plan"));
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].snippet.is_none());
+    }
+}
@@ -0,0 +1,205 @@
+//! Private staging area for decrypted notes handed to an external editor.
+//!
+//! Plaintext is written to a per-user directory on a RAM-backed filesystem
+//! when one is available (`$XDG_RUNTIME_DIR`, then `/dev/shm`), never next
+//! to the encrypted notes. Each vault stages into its own subdirectory, so
+//! one vault never mistakes another's staged edits for its own. Files are
+//! created with mode 0600 inside a 0700 directory and are wiped and removed
+//! when their [`PlaintextFile`] guard is dropped, including during a panic.
+//! Files left behind by a killed process are found by [`orphans`] on the
+//! next start.
+
+use crate::crypto::sha256_hex;
+use crate::error::Result;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// A decrypted note staged for editing. Wiped and deleted on drop.
+pub struct PlaintextFile {
+    path: PathBuf,
+}
+
+impl PlaintextFile {
+    /// Stages `content` for the note `note_uuid` of the vault whose database
+    /// is `db_path`. The extension is kept so editors pick the right syntax
+    /// highlighting.
+    pub fn create(db_path: &Path, note_uuid: &str, extension: &str, content: &[u8]) -> Result<Self> {
+        let dir = staging_dir(db_path)?;
+        let path = dir.join(format!(
+            "{}.{}.{}",
+            note_uuid,
+            std::process::id(),
+            extension
+        ));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path)?;
+        let guard = Self { path };
+        file.write_all(content)?;
+        file.sync_all()?;
+        Ok(guard)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read(&self) -> Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(fs::read(&self.path)?))
+    }
+}
+
+impl Drop for PlaintextFile {
+    fn drop(&mut self) {
+        if let Err(e) = wipe(&self.path) {
+            log::warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// A staged file whose owning process is gone.
+#[derive(Debug)]
+pub struct Orphan {
+    pub path: PathBuf,
+    pub note_uuid: String,
+}
+
+impl Orphan {
+    pub fn read(&self) -> Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(fs::read(&self.path)?))
+    }
+
+    /// Wipes the orphan along with any editor swap or backup files that
+    /// were created next to it.
+    pub fn remove(self) -> Result<()> {
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if let Some(dir) = self.path.parent() {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let other = entry.file_name().to_string_lossy().to_string();
+                if other != name && other.contains(&name) {
+                    wipe(&entry.path())?;
+                }
+            }
+        }
+        wipe(&self.path)
+    }
+}
+
+/// Staged files of the vault at `db_path` left behind by editing sessions
+/// that did not exit cleanly.
+pub fn orphans(db_path: &Path) -> Result<Vec<Orphan>> {
+    let dir = staging_dir(db_path)?;
+    let mut orphans = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let mut parts = name.splitn(3, '.');
+        let (Some(uuid), Some(pid), Some(_)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        let Ok(pid) = pid.parse::<u32>() else {
+            continue;
+        };
+        if uuid.is_empty() || process_alive(pid) {
+            continue;
+        }
+        orphans.push(Orphan {
+            path: entry.path(),
+            note_uuid: uuid.to_string(),
+        });
+    }
+    Ok(orphans)
+}
+
+/// Overwrites a file with zeros before unlinking it.
+pub fn wipe(path: &Path) -> Result<()> {
+    let len = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    if let Ok(mut file) = fs::OpenOptions::new().write(true).open(path) {
+        let zeros = vec![0u8; 8192];
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = remaining.min(zeros.len() as u64) as usize;
+            file.write_all(&zeros[..chunk])?;
+            remaining -= chunk as u64;
+        }
+        file.sync_all()?;
+    }
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+fn staging_dir(db_path: &Path) -> Result<PathBuf> {
+    let base = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+        .map(|dir| dir.join("noters"))
+        .or_else(|| {
+            let shm = Path::new("/dev/shm");
+            shm.is_dir().then(|| shm.join(format!("noters-{}", user_id())))
+        })
+        .unwrap_or_else(|| std::env::temp_dir().join(format!("noters-{}", user_id())));
+
+    fs::create_dir_all(&base)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+        let metadata = fs::symlink_metadata(&base)?;
+        if !metadata.is_dir() || metadata.uid() != user_id() {
+            return Err(crate::error::NoterError::EditorError(format!(
+                "Refusing to use staging directory {} not owned by the current user",
+                base.display()
+            )));
+        }
+        fs::set_permissions(&base, fs::Permissions::from_mode(0o700))?;
+    }
+
+    let db_path = fs::canonicalize(db_path).unwrap_or_else(|_| db_path.to_path_buf());
+    let vault = sha256_hex(db_path.to_string_lossy().as_bytes());
+    let dir = base.join(&vault[..16]);
+    fs::create_dir_all(&dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+#[cfg(unix)]
+fn user_id() -> u32 {
+    // SAFETY: getuid has no preconditions and cannot fail.
+    unsafe { libc::getuid() }
+}
+
+#[cfg(not(unix))]
+fn user_id() -> u32 {
+    0
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    // SAFETY: signal 0 only checks whether the process exists.
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(pid: u32) -> bool {
+    pid == std::process::id()
+}
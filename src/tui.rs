@@ -0,0 +1,394 @@
+//! The full-screen interface behind `noters tui`: a note list with a live
+//! fuzzy title filter beside a preview of the selected note.
+
+use crate::db::NoteRecord;
+use crate::error::{NoterError, Result};
+use crate::frontmatter::NoteMetadata;
+use crate::fuzzy;
+use crate::note::NotesManager;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal::{self, EnterAlternateScreen};
+use ratatui::crossterm::execute;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use zeroize::Zeroizing;
+
+const HELP: &str = "/ filter  n new  e edit  r rename  t tag  d delete  PgUp/PgDn scroll  q quit";
+
+/// Runs the interface until the user quits. The terminal is restored
+/// whether or not an error ends the session.
+pub fn run(notes: &NotesManager) -> Result<()> {
+    let mut app = App::new(notes)?;
+    let mut terminal = ratatui::try_init()?;
+    let result = app.run(&mut terminal);
+    ratatui::try_restore()?;
+    result
+}
+
+enum Mode {
+    Normal,
+    Filter,
+    Input { action: InputAction, text: String },
+    ConfirmDelete,
+}
+
+#[derive(Clone, Copy)]
+enum InputAction {
+    Create,
+    Rename,
+    Tag,
+}
+
+impl InputAction {
+    fn prompt(self) -> &'static str {
+        match self {
+            Self::Create => "New note title: ",
+            Self::Rename => "Rename to: ",
+            Self::Tag => "Tags (comma separated): ",
+        }
+    }
+}
+
+struct App<'a> {
+    notes: &'a NotesManager,
+    all: Vec<NoteRecord>,
+    /// Indices into `all` of the notes matching the filter.
+    visible: Vec<usize>,
+    list: ListState,
+    filter: String,
+    mode: Mode,
+    /// The decrypted content of the selected note, read once per selection.
+    preview: Option<(i64, Zeroizing<String>)>,
+    scroll: u16,
+    /// The result of the last action, shown in the status bar.
+    status: Option<(String, bool)>,
+    quit: bool,
+}
+
+impl<'a> App<'a> {
+    fn new(notes: &'a NotesManager) -> Result<Self> {
+        let mut app = Self {
+            notes,
+            all: Vec::new(),
+            visible: Vec::new(),
+            list: ListState::default(),
+            filter: String::new(),
+            mode: Mode::Normal,
+            preview: None,
+            scroll: 0,
+            status: None,
+            quit: false,
+        };
+        app.reload(None)?;
+        Ok(app)
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            self.load_preview();
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.key(key, terminal)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-reads the note list, keeping `select` (or the current note)
+    /// selected if it still matches the filter.
+    fn reload(&mut self, select: Option<i64>) -> Result<()> {
+        let select = select.or_else(|| self.selected().map(|note| note.id));
+        self.all = self.notes.list_notes()?;
+        self.preview = None;
+        self.apply_filter(select);
+        Ok(())
+    }
+
+    fn apply_filter(&mut self, select: Option<i64>) {
+        self.visible = (0..self.all.len())
+            .filter(|&i| fuzzy::score(&self.filter, &self.all[i].title).is_some())
+            .collect();
+        let index = select
+            .and_then(|id| self.visible.iter().position(|&i| self.all[i].id == id))
+            .or(if self.visible.is_empty() { None } else { Some(0) });
+        self.select(index);
+    }
+
+    fn select(&mut self, index: Option<usize>) {
+        if self.list.selected() != index {
+            self.scroll = 0;
+        }
+        self.list.select(index);
+    }
+
+    fn selected(&self) -> Option<&NoteRecord> {
+        let index = *self.visible.get(self.list.selected()?)?;
+        self.all.get(index)
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let last = self.visible.len() as isize - 1;
+        let current = self.list.selected().unwrap_or(0) as isize;
+        self.select(Some((current + delta).clamp(0, last) as usize));
+    }
+
+    fn load_preview(&mut self) {
+        let Some(id) = self.selected().map(|note| note.id) else {
+            self.preview = None;
+            return;
+        };
+        if self.preview.as_ref().is_some_and(|(previewed, _)| *previewed == id) {
+            return;
+        }
+        match self.notes.read_note(id) {
+            Ok(content) => self.preview = Some((id, content)),
+            Err(e) => {
+                self.preview = Some((id, Zeroizing::new(String::new())));
+                self.error(e);
+            }
+        }
+    }
+
+    fn error(&mut self, e: NoterError) {
+        self.status = Some((e.to_string(), true));
+    }
+
+    fn info(&mut self, message: String) {
+        self.status = Some((message, false));
+    }
+
+    fn key(&mut self, key: KeyEvent, terminal: &mut DefaultTerminal) -> Result<()> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return Ok(());
+        }
+
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.normal_key(key, terminal)?,
+            Mode::Filter => match key.code {
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.apply_filter(None);
+                }
+                KeyCode::Enter => {}
+                KeyCode::Up => self.keep(Mode::Filter, |app| app.move_selection(-1)),
+                KeyCode::Down => self.keep(Mode::Filter, |app| app.move_selection(1)),
+                KeyCode::Backspace => self.keep(Mode::Filter, |app| {
+                    app.filter.pop();
+                    app.apply_filter(None);
+                }),
+                KeyCode::Char(c) => self.keep(Mode::Filter, |app| {
+                    app.filter.push(c);
+                    app.apply_filter(None);
+                }),
+                _ => self.mode = Mode::Filter,
+            },
+            Mode::Input { action, mut text } => match key.code {
+                KeyCode::Esc => {}
+                KeyCode::Enter => self.submit(action, &text)?,
+                KeyCode::Backspace => {
+                    text.pop();
+                    self.mode = Mode::Input { action, text };
+                }
+                KeyCode::Char(c) => {
+                    text.push(c);
+                    self.mode = Mode::Input { action, text };
+                }
+                _ => self.mode = Mode::Input { action, text },
+            },
+            Mode::ConfirmDelete => {
+                if key.code == KeyCode::Char('y') {
+                    self.delete()?;
+                } else {
+                    self.info("Delete cancelled.".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn keep(&mut self, mode: Mode, action: impl FnOnce(&mut Self)) {
+        action(self);
+        self.mode = mode;
+    }
+
+    fn normal_key(&mut self, key: KeyEvent, terminal: &mut DefaultTerminal) -> Result<()> {
+        self.status = None;
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Esc if !self.filter.is_empty() => {
+                self.filter.clear();
+                self.apply_filter(None);
+            }
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Char('/') => self.mode = Mode::Filter,
+            KeyCode::Char('n') => self.start_input(InputAction::Create, String::new()),
+            KeyCode::Char('r') => {
+                if let Some(title) = self.selected().map(|note| note.title.to_string()) {
+                    self.start_input(InputAction::Rename, title);
+                }
+            }
+            KeyCode::Char('t') => {
+                if let Some((_, content)) = &self.preview {
+                    let tags = NoteMetadata::from_content(content).tags.join(", ");
+                    self.start_input(InputAction::Tag, tags);
+                }
+            }
+            KeyCode::Char('d') if self.selected().is_some() => self.mode = Mode::ConfirmDelete,
+            KeyCode::Char('e') | KeyCode::Enter => self.edit(terminal)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn start_input(&mut self, action: InputAction, text: String) {
+        self.mode = Mode::Input { action, text };
+    }
+
+    fn submit(&mut self, action: InputAction, text: &str) -> Result<()> {
+        let id = self.selected().map(|note| note.id);
+        let result = match (action, id) {
+            (InputAction::Create, _) => self.notes.create_note(text.trim(), "").map(|id| {
+                // Show the new note even if it does not match the filter.
+                self.filter.clear();
+                (Some(id), "Note created.")
+            }),
+            (InputAction::Rename, Some(id)) => {
+                self.notes.rename_note(id, text).map(|()| (Some(id), "Note renamed."))
+            }
+            (InputAction::Tag, Some(id)) => {
+                let tags: Vec<String> = text
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                self.notes.set_tags(id, &tags).map(|()| (Some(id), "Tags updated."))
+            }
+            (_, None) => return Ok(()),
+        };
+        match result {
+            Ok((select, message)) => {
+                self.reload(select)?;
+                self.info(message.to_string());
+            }
+            Err(e) => self.error(e),
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self) -> Result<()> {
+        let Some(id) = self.selected().map(|note| note.id) else {
+            return Ok(());
+        };
+        match self.notes.delete_note(id) {
+            Ok(_) => {
+                let index = self.list.selected();
+                self.reload(None)?;
+                let last = self.visible.len().checked_sub(1);
+                self.select(index.zip(last).map(|(index, last)| index.min(last)));
+                self.info("Note deleted.".to_string());
+            }
+            Err(e) => self.error(e),
+        }
+        Ok(())
+    }
+
+    /// Hands the terminal to the editor for the selected note and takes it
+    /// back afterwards.
+    fn edit(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let Some(id) = self.selected().map(|note| note.id) else {
+            return Ok(());
+        };
+        ratatui::try_restore()?;
+        let result = self.notes.edit_note(id);
+        terminal::enable_raw_mode()?;
+        execute!(std::io::stdout(), EnterAlternateScreen)?;
+        terminal.clear()?;
+
+        match result {
+            Ok(()) => {
+                self.reload(Some(id))?;
+                self.info("Note saved.".to_string());
+            }
+            Err(e) => self.error(e),
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)])
+            .areas(frame.area());
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let note = &self.all[i];
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{:>4} ", note.id), Style::new().fg(Color::Cyan)),
+                    Span::raw(note.title.as_str()),
+                ]))
+            })
+            .collect();
+        let title = if self.filter.is_empty() {
+            format!(" Notes ({}) ", self.all.len())
+        } else {
+            format!(" Notes ({}/{}) /{} ", self.visible.len(), self.all.len(), self.filter)
+        };
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let preview_title = self
+            .selected()
+            .map(|note| format!(" {} ", note.title.as_str()))
+            .unwrap_or_default();
+        let content = self.preview.as_ref().map(|(_, content)| content.as_str()).unwrap_or("");
+        let preview = Paragraph::new(content)
+            .block(Block::bordered().title(preview_title))
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        frame.render_widget(preview, preview_area);
+
+        let line = match &self.mode {
+            Mode::Filter => Line::from(format!("/{}", self.filter)),
+            Mode::Input { action, text } => Line::from(format!("{}{}", action.prompt(), text)),
+            Mode::ConfirmDelete => Line::from("Delete this note? (y/n)").yellow(),
+            Mode::Normal => match &self.status {
+                Some((message, true)) => Line::from(message.as_str()).red(),
+                Some((message, false)) => Line::from(message.as_str()).green(),
+                None => Line::from(HELP).dim(),
+            },
+        };
+        frame.render_widget(Paragraph::new(line), status);
+        if let Mode::Filter | Mode::Input { .. } = self.mode {
+            let width = frame.buffer_mut().area.width.saturating_sub(1);
+            let text_width = match &self.mode {
+                Mode::Filter => self.filter.chars().count() + 1,
+                Mode::Input { action, text } => action.prompt().len() + text.chars().count(),
+                _ => 0,
+            };
+            frame.set_cursor_position((status.x + (text_width as u16).min(width), status.y));
+        }
+    }
+}
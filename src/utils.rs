@@ -1,4 +1,12 @@
+use crate::error::{NoterError, Result};
+use colored::Colorize;
 use std::io::{self, Write};
+use std::process::Command;
+use zeroize::Zeroizing;
+
+pub const PASSPHRASE_ENV: &str = "NOTERS_PASSPHRASE";
+pub const NEW_PASSPHRASE_ENV: &str = "NOTERS_NEW_PASSPHRASE";
+pub const BACKUP_PASSPHRASE_ENV: &str = "NOTERS_BACKUP_PASSPHRASE";
 
 pub fn get_input(prompt: &str) -> String {
     print!("{}", prompt);
@@ -9,3 +17,191 @@ pub fn get_input(prompt: &str) -> String {
         .expect("Failed to read input");
     input.trim().to_string()
 }
+
+/// Asks the user to choose one of `options` by number and returns its
+/// index, or `None` if the answer is left empty. Everything is written to
+/// stderr so the command's own output stays clean.
+pub fn pick(heading: &str, options: &[String]) -> Result<Option<usize>> {
+    let mut stderr = io::stderr();
+    writeln!(stderr, "{}", heading)?;
+    for (i, option) in options.iter().enumerate() {
+        writeln!(stderr, "  {} {}", format!("{:>2})", i + 1).cyan(), option)?;
+    }
+    loop {
+        write!(stderr, "Select a note [1-{}]: ", options.len())?;
+        stderr.flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=options.len()).contains(&n) => return Ok(Some(n - 1)),
+            _ => writeln!(stderr, "{}", "Enter one of the numbers above.".yellow())?,
+        }
+    }
+}
+
+/// Asks a yes/no question on stderr; anything but "y" or "yes" is a no.
+pub fn confirm(prompt: &str) -> Result<bool> {
+    let mut stderr = io::stderr();
+    write!(stderr, "{} [y/N]: ", prompt)?;
+    stderr.flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Reads everything piped to stdin, e.g. the body of a new note.
+pub fn read_stdin() -> Result<Zeroizing<String>> {
+    let mut input = Zeroizing::new(String::new());
+    io::Read::read_to_string(&mut io::stdin(), &mut input)?;
+    Ok(input)
+}
+
+/// Resolves the master passphrase from `$NOTERS_PASSPHRASE`, the configured
+/// passphrase command, or an interactive prompt, in that order.
+pub fn get_passphrase(command: Option<&str>, confirm: bool) -> Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+
+    if let Some(command) = command {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .output()
+            .map_err(|e| NoterError::Config(format!("Passphrase command failed: {}", e)))?;
+        if !output.status.success() {
+            return Err(NoterError::Config(
+                "Passphrase command exited with non-zero status".to_string(),
+            ));
+        }
+        let passphrase = crate::crypto::into_string(Zeroizing::new(output.stdout))
+            .map_err(|e| NoterError::Config(format!("Passphrase command output: {}", e)))?;
+        return Ok(Zeroizing::new(
+            passphrase.trim_end_matches(['\r', '\n']).to_string(),
+        ));
+    }
+
+    const HINT: &str = "set NOTERS_PASSPHRASE or passphrase_command in config.toml";
+    let passphrase = prompt_passphrase("Passphrase: ", HINT)?;
+    if confirm {
+        let repeated = prompt_passphrase("Confirm passphrase: ", HINT)?;
+        if repeated != passphrase {
+            return Err(NoterError::InvalidInput("Passphrases do not match".to_string()));
+        }
+    }
+    if passphrase.is_empty() {
+        return Err(NoterError::InvalidInput("Passphrase cannot be empty".to_string()));
+    }
+    Ok(passphrase)
+}
+
+/// Reads a passphrase from the terminal. Without one (cron jobs, pipes) the
+/// error carries `hint` on how to pass it instead.
+fn prompt_passphrase(prompt: &str, hint: &str) -> Result<Zeroizing<String>> {
+    rpassword::prompt_password(prompt)
+        .map(Zeroizing::new)
+        .map_err(|e| NoterError::PassphraseUnavailable(format!("{} ({})", hint, e)))
+}
+
+/// Asks for the passphrase a vault is being re-keyed to, from
+/// `$NOTERS_NEW_PASSPHRASE` or an interactive prompt with confirmation.
+pub fn get_new_passphrase() -> Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(NEW_PASSPHRASE_ENV) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+
+    const HINT: &str = "set NOTERS_NEW_PASSPHRASE";
+    let passphrase = prompt_passphrase("New passphrase: ", HINT)?;
+    let repeated = prompt_passphrase("Confirm new passphrase: ", HINT)?;
+    if repeated != passphrase {
+        return Err(NoterError::InvalidInput("Passphrases do not match".to_string()));
+    }
+    if passphrase.is_empty() {
+        return Err(NoterError::InvalidInput("Passphrase cannot be empty".to_string()));
+    }
+    Ok(passphrase)
+}
+
+/// Asks for the passphrase protecting a backup archive, from
+/// `$NOTERS_BACKUP_PASSPHRASE` or an interactive prompt. New archives are
+/// confirmed.
+pub fn get_backup_passphrase(confirm: bool) -> Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(BACKUP_PASSPHRASE_ENV) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+
+    const HINT: &str = "set NOTERS_BACKUP_PASSPHRASE";
+    let passphrase = prompt_passphrase("Backup passphrase: ", HINT)?;
+    if confirm {
+        let repeated = prompt_passphrase("Confirm backup passphrase: ", HINT)?;
+        if repeated != passphrase {
+            return Err(NoterError::InvalidInput("Passphrases do not match".to_string()));
+        }
+    }
+    if passphrase.is_empty() {
+        return Err(NoterError::InvalidInput("Passphrase cannot be empty".to_string()));
+    }
+    Ok(passphrase)
+}
+
+/// Rows of the terminal on stdout, if it is one.
+pub fn terminal_height() -> Option<usize> {
+    #[cfg(unix)]
+    {
+        // SAFETY: winsize is plain integers, so all zeroes is a valid value.
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        // SAFETY: TIOCGWINSZ only writes a winsize into the pointer, which is
+        // valid for the duration of the call; on failure it is left zeroed.
+        let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if result == 0 && size.ws_row > 0 {
+            return Some(size.ws_row as usize);
+        }
+    }
+    std::env::var("LINES").ok().and_then(|lines| lines.parse().ok())
+}
+
+/// Shows `text` through `$PAGER` (`less` by default), or prints it when no
+/// pager can be started. `less` is told to pass colors through and to exit
+/// straight away when the text fits on one screen.
+pub fn page(text: &str) -> Result<()> {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less".to_string());
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .env("LESS", std::env::var("LESS").unwrap_or_else(|_| "FRX".to_string()))
+        .stdin(std::process::Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            log::warn!("Could not start pager '{}': {}", pager, e);
+            print!("{}", text);
+            return Ok(());
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may be quit before reading everything.
+        if let Err(e) = stdin.write_all(text.as_bytes()) {
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                return Err(e.into());
+            }
+        }
+    }
+    // `sh` itself always starts; 126 and 127 mean it could not run the pager.
+    let status = child.wait()?;
+    if matches!(status.code(), Some(126 | 127)) {
+        log::warn!("Could not start pager '{}': {}", pager, status);
+        print!("{}", text);
+    }
+    Ok(())
+}